@@ -1,21 +1,14 @@
 use piston::{Event, ResizeArgs, ResizeEvent, Window};
-use wgpu::{Adapter, Device, PresentMode, Surface, SurfaceConfiguration, TextureFormat};
+use wgpu::{Adapter, Device, Surface, SurfaceConfiguration};
 use winit_window::WinitWindow;
 
 pub fn init_surface_config(
-    _surface: &Surface,
-    _adapter: &Adapter,
+    surface: &Surface,
+    adapter: &Adapter,
     window: &WinitWindow,
 ) -> SurfaceConfiguration {
-    SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: TextureFormat::Bgra8UnormSrgb,
-        width: window.draw_size().width as u32,
-        height: window.draw_size().height as u32,
-        present_mode: PresentMode::Fifo,
-        alpha_mode: wgpu::CompositeAlphaMode::PostMultiplied,
-        view_formats: vec![TextureFormat::Bgra8UnormSrgb],
-    }
+    let size = window.draw_size();
+    wgpu_graphics::default_surface_config(surface, adapter, [size.width as u32, size.height as u32])
 }
 
 pub fn event_resize(
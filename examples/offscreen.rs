@@ -0,0 +1,79 @@
+mod include;
+
+use crate::include::{event_resize, init_surface_config};
+use graphics::{clear, Ellipse, Image, Rectangle, Transformed};
+use piston::{EventSettings, Events, RenderEvent, WindowSettings};
+use texture::TextureSettings;
+use winit_window::WinitWindow;
+
+fn main() {
+    let mut window = WinitWindow::new(&WindowSettings::new("wgpu_graphics: offscreen", (640, 480)));
+
+    let instance = wgpu::Instance::new(Default::default());
+    let surface = unsafe { instance.create_surface(window.get_window()) }.unwrap();
+    let adapter =
+        futures::executor::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface: Some(&surface),
+            ..Default::default()
+        }))
+        .unwrap();
+
+    let mut device_descriptor = wgpu::DeviceDescriptor::default();
+    device_descriptor
+        .features
+        .set(wgpu::Features::DEPTH_CLIP_CONTROL, true);
+    let (device, queue) =
+        futures::executor::block_on(adapter.request_device(&device_descriptor, None)).unwrap();
+    let mut surface_config = init_surface_config(&surface, &adapter, &window);
+    surface.configure(&device, &surface_config);
+
+    let mut wgpu2d = wgpu_graphics::Wgpu2d::new(&device, &surface_config);
+    // Rendered once, at a size unrelated to the window.
+    let offscreen = wgpu2d.create_render_target(&device, [512, 512], &TextureSettings::new());
+    let command_buffer = wgpu2d
+        .draw_to_texture(&device, &offscreen, |c, g| {
+            clear([0.2, 0.2, 0.4, 1.0], g);
+            Rectangle::new([1.0, 0.8, 0.0, 1.0]).draw(
+                [64.0, 64.0, 384.0, 384.0],
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+            Ellipse::new([0.0, 0.6, 1.0, 1.0]).draw(
+                [128.0, 128.0, 256.0, 256.0],
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+        })
+        .unwrap();
+    queue.submit(std::iter::once(command_buffer));
+
+    let mut events = Events::new(EventSettings::new());
+    while let Some(event) = events.next(&mut window) {
+        event_resize(&event, &device, &surface, &mut surface_config);
+        event.render(|render_args| {
+            let surface_texture = surface.get_current_texture().unwrap();
+            let surface_view = surface_texture
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+
+            let command_buffer = wgpu2d.draw(
+                &device,
+                &surface_config,
+                &surface_view,
+                render_args.viewport(),
+                |c, g| {
+                    clear([0.8, 0.8, 0.8, 1.0], g);
+                    // Samples the offscreen texture, scaled down in two copies.
+                    let transform = c.transform.trans(40.0, 40.0).scale(0.5, 0.5);
+                    Image::new().draw(&offscreen, &c.draw_state, transform, g);
+                    let transform = c.transform.trans(340.0, 140.0).scale(0.5, 0.5);
+                    Image::new().draw(&offscreen, &c.draw_state, transform, g);
+                },
+            );
+            queue.submit(std::iter::once(command_buffer));
+            surface_texture.present();
+        });
+    }
+}
@@ -20,11 +20,11 @@ fn main() {
         .unwrap();
 
     let mut device_descriptor = wgpu::DeviceDescriptor::default();
-    device_descriptor.features.set(wgpu::Features::DEPTH_CLIP_CONTROL, true);
-    let (device, queue) = futures::executor::block_on(
-        adapter.request_device(&device_descriptor, None),
-    )
-    .unwrap();
+    device_descriptor
+        .features
+        .set(wgpu::Features::DEPTH_CLIP_CONTROL, true);
+    let (device, queue) =
+        futures::executor::block_on(adapter.request_device(&device_descriptor, None)).unwrap();
 
     let mut surface_config = init_surface_config(&surface, &adapter, &window);
     surface.configure(&device, &surface_config);
@@ -0,0 +1,130 @@
+//! A renderer bundled with the surface of a single window.
+
+use crate::{TextureContext, Wgpu2d, Wgpu2dSettings, WgpuGraphics, REQUIRED_FEATURES};
+use graphics::{Context, Viewport};
+
+/// The surface of a single window, its configuration and the [`Wgpu2d`] drawing onto it.
+///
+/// This handles acquiring, submitting and presenting frames, and reconfiguring the surface.
+/// The device and queue are borrowed, as the `Wgpu2d` borrows the device.
+pub struct App2d<'a> {
+    device: &'a wgpu::Device,
+    queue: &'a wgpu::Queue,
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+    wgpu2d: Wgpu2d<'a>,
+}
+
+impl<'a> App2d<'a> {
+    /// Returns the features the device must be requested with, [`REQUIRED_FEATURES`].
+    pub fn required_features() -> wgpu::Features {
+        REQUIRED_FEATURES
+    }
+
+    /// Creates a new `App2d`, configuring `surface` with `config`.
+    pub fn new(
+        device: &'a wgpu::Device,
+        queue: &'a wgpu::Queue,
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+    ) -> Self {
+        App2d::with_settings(device, queue, surface, config, &Wgpu2dSettings::default())
+    }
+
+    /// Creates a new `App2d` with `settings`, configuring `surface` with `config`.
+    pub fn with_settings(
+        device: &'a wgpu::Device,
+        queue: &'a wgpu::Queue,
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+        settings: &Wgpu2dSettings,
+    ) -> Self {
+        surface.configure(device, &config);
+        let wgpu2d = Wgpu2d::with_settings(device, &config, settings);
+        App2d {
+            device,
+            queue,
+            surface,
+            config,
+            wgpu2d,
+        }
+    }
+
+    /// Returns the device.
+    pub fn device(&self) -> &'a wgpu::Device {
+        self.device
+    }
+
+    /// Returns the queue.
+    pub fn queue(&self) -> &'a wgpu::Queue {
+        self.queue
+    }
+
+    /// Returns the current configuration of the surface.
+    pub fn config(&self) -> &wgpu::SurfaceConfiguration {
+        &self.config
+    }
+
+    /// Returns the `Wgpu2d` drawing onto the surface.
+    pub fn wgpu2d(&self) -> &Wgpu2d<'a> {
+        &self.wgpu2d
+    }
+
+    /// Returns the `Wgpu2d` drawing onto the surface, e.g. to draw into textures.
+    pub fn wgpu2d_mut(&mut self) -> &mut Wgpu2d<'a> {
+        &mut self.wgpu2d
+    }
+
+    /// Returns a new `TextureContext` creating textures on the device, for the format of the surface.
+    pub fn texture_context(&self) -> TextureContext<'a> {
+        let mut context = TextureContext::from_parts(self.device, self.queue);
+        context.set_target_format(self.wgpu2d.format());
+        context
+    }
+
+    /// Reconfigures the surface to `size`, in pixels, e.g. when the window is resized.
+    ///
+    /// Empty sizes, as of minimized windows, are ignored.
+    pub fn resize(&mut self, [width, height]: [u32; 2]) {
+        if width == 0 || height == 0 || [width, height] == [self.config.width, self.config.height] {
+            return;
+        }
+        self.config.width = width;
+        self.config.height = height;
+        self.surface.configure(self.device, &self.config);
+        self.wgpu2d.resize(&self.config);
+    }
+
+    /// Acquires the next frame of the surface, draws it with `f`, then submits and presents it.
+    ///
+    /// If the surface is lost or outdated, it is reconfigured and the frame is skipped,
+    /// and the error is returned, as for timeouts; running out of memory isn't recoverable.
+    pub fn render<F>(&mut self, viewport: Viewport, f: F) -> Result<(), wgpu::SurfaceError>
+    where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        let surface_texture = match self.surface.get_current_texture() {
+            Ok(surface_texture) => surface_texture,
+            Err(e) => {
+                if let wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated = e {
+                    self.surface.configure(self.device, &self.config);
+                }
+                return Err(e);
+            }
+        };
+        let surface_view = surface_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let command_buffer =
+            self.wgpu2d
+                .draw(self.device, &self.config, &surface_view, viewport, f);
+        self.queue.submit(std::iter::once(command_buffer));
+        let suboptimal = surface_texture.suboptimal;
+        surface_texture.present();
+        if suboptimal {
+            self.surface.configure(self.device, &self.config);
+        }
+        Ok(())
+    }
+}
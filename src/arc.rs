@@ -0,0 +1,216 @@
+//! Arcs and pie slices computed per pixel by a fragment shader.
+
+use crate::{transform_position, BatchKind, PsoStencil, TexturedPipelineInput, WgpuGraphics};
+use graphics::{
+    draw_state::{Blend, Stencil},
+    types::{Color, Matrix2d},
+    DrawState,
+};
+use std::f64::consts::PI;
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+/// Uniforms of the "arc" shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ArcParams {
+    /// Inner radius relative to the outer radius, half the angle covered,
+    /// and cosine and sine of the angle of the middle of the arc.
+    params: [f32; 4],
+}
+
+/// Pipelines drawing arcs with the "textured" vertex layout,
+/// where UVs are positions relative to the center, in units of the outer radius.
+pub(crate) struct ArcPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipelines: PsoStencil<wgpu::RenderPipeline>,
+}
+
+impl ArcPipelines {
+    /// Creates new `ArcPipelines`, using `f` with a pipeline layout, shader module and fragment entry point,
+    /// for all Stencil and Blend modes.
+    pub(crate) fn new<F>(device: &wgpu::Device, mut f: F) -> Self
+    where
+        F: FnMut(
+            &str,
+            &wgpu::PipelineLayout,
+            &wgpu::ShaderModule,
+            &str,
+            Option<wgpu::BlendState>,
+            wgpu::StencilState,
+        ) -> wgpu::RenderPipeline,
+    {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Arc Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Arc Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::include_wgsl!("arc.wgsl"));
+
+        let pipelines = PsoStencil::new(|blend, stencil| {
+            f(
+                "Arc Render Pipeline",
+                &pipeline_layout,
+                &shader_module,
+                "fs_main",
+                blend,
+                stencil,
+            )
+        });
+
+        ArcPipelines {
+            bind_group_layout,
+            pipelines,
+        }
+    }
+
+    /// Returns the pipeline for `stencil` and `blend`, and the stencil reference value to draw it with.
+    pub(crate) fn stencil_blend(
+        &self,
+        stencil: Option<Stencil>,
+        blend: Option<Blend>,
+    ) -> (&wgpu::RenderPipeline, Option<u8>) {
+        self.pipelines.stencil_blend(stencil, blend)
+    }
+
+    /// Creates the bind group of an arc from `start_angle` to `end_angle`, in radians,
+    /// whose inner radius is `inner_ratio` times its outer radius.
+    pub(crate) fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        inner_ratio: f64,
+        start_angle: f64,
+        end_angle: f64,
+    ) -> Arc<wgpu::BindGroup> {
+        let half_span = 0.5 * (end_angle - start_angle).abs().min(2.0 * PI);
+        let (sin_mid, cos_mid) = (0.5 * (start_angle + end_angle)).sin_cos();
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Arc Params Buffer"),
+            contents: bytemuck::bytes_of(&ArcParams {
+                params: [
+                    inner_ratio as f32,
+                    half_span as f32,
+                    cos_mid as f32,
+                    sin_mid as f32,
+                ],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        Arc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Arc Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            }],
+        }))
+    }
+}
+
+impl<'a> WgpuGraphics<'a> {
+    /// Draws an arc of `color` around `center`, with a stroke of `thickness` centered on `radius`.
+    ///
+    /// The arc goes from `start_angle` to `end_angle`, in radians clockwise from the x axis.
+    /// It is computed per pixel on a bounding quad, so its edges stay smooth at any size, unlike tessellated arcs.
+    #[allow(clippy::too_many_arguments)]
+    pub fn arc(
+        &mut self,
+        draw_state: &DrawState,
+        center: [f64; 2],
+        radius: f64,
+        start_angle: f64,
+        end_angle: f64,
+        thickness: f64,
+        transform: Matrix2d,
+        color: Color,
+    ) {
+        let half_thickness = 0.5 * thickness.abs();
+        self.push_arc(
+            draw_state,
+            center,
+            [(radius - half_thickness).max(0.0), radius + half_thickness],
+            [start_angle, end_angle],
+            transform,
+            color,
+        );
+    }
+
+    /// Draws a pie slice of `color` and `radius` around `center`,
+    /// from `start_angle` to `end_angle`, in radians clockwise from the x axis.
+    ///
+    /// As with [`WgpuGraphics::arc`], its edges stay smooth at any size.
+    #[allow(clippy::too_many_arguments)]
+    pub fn pie(
+        &mut self,
+        draw_state: &DrawState,
+        center: [f64; 2],
+        radius: f64,
+        start_angle: f64,
+        end_angle: f64,
+        transform: Matrix2d,
+        color: Color,
+    ) {
+        self.push_arc(
+            draw_state,
+            center,
+            [0.0, radius],
+            [start_angle, end_angle],
+            transform,
+            color,
+        );
+    }
+
+    /// Pushes the bounding quad of an arc between the `[inner, outer]` radii and the `[start, end]` angles.
+    fn push_arc(
+        &mut self,
+        draw_state: &DrawState,
+        [cx, cy]: [f64; 2],
+        [inner, outer]: [f64; 2],
+        [start_angle, end_angle]: [f64; 2],
+        transform: Matrix2d,
+        color: Color,
+    ) {
+        if outer <= 0.0 || inner >= outer {
+            return;
+        }
+        let bind_group = self.wgpu2d.arc_pipelines.bind_group(
+            self.wgpu2d.device,
+            inner / outer,
+            start_angle,
+            end_angle,
+        );
+
+        let corner = |u: f64, v: f64| TexturedPipelineInput {
+            xy: transform_position(transform, [cx + u * outer, cy + v * outer]),
+            uv: [u as f32, v as f32],
+            color,
+            alpha_test: 0.0,
+        };
+        self.push_quad(
+            BatchKind::CircleArc(bind_group),
+            draw_state,
+            [
+                corner(-1.0, -1.0),
+                corner(1.0, -1.0),
+                corner(1.0, 1.0),
+                corner(-1.0, 1.0),
+            ],
+        );
+    }
+}
@@ -0,0 +1,48 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct ArcParams {
+    params: vec4<f32>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> arc: ArcParams;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+    out.uv = in.uv;
+    out.color = in.color;
+    return out;
+}
+
+// Covers the ring between the inner radius and 1, within half the span of the middle angle,
+// antialiased over about a pixel at any scale.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let inner_ratio = arc.params.x;
+    let half_span = arc.params.y;
+    let mid = arc.params.zw;
+
+    let r = length(in.uv);
+    let radial = max(inner_ratio - r, r - 1.0);
+    // Rotated so the middle of the arc is along the x axis.
+    let p = vec2<f32>(dot(in.uv, mid), in.uv.y * mid.x - in.uv.x * mid.y);
+    let angular = (abs(atan2(p.y, p.x)) - half_span) * r;
+    let distance = max(radial, angular);
+
+    let smoothing = max(fwidth(distance), 0.0001);
+    let coverage = clamp(0.5 - distance / smoothing, 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,96 @@
+//! Packing of several images into one texture.
+
+use crate::{Texture, TextureContext, TextureError};
+use image::{GenericImage, RgbaImage};
+use texture::TextureSettings;
+
+/// Empty pixels kept between packed images, so filtering doesn't bleed neighbours in.
+const PADDING: u32 = 1;
+
+/// A texture holding several images, which can be drawn without switching textures.
+pub struct TextureAtlas {
+    texture: Texture,
+    uv_rects: Vec<[f32; 4]>,
+}
+
+impl TextureAtlas {
+    /// Packs `images` into one texture, using shelf packing.
+    ///
+    /// Returns an error if the packed images don't fit in a texture the device supports.
+    pub fn build<'a>(
+        context: &mut TextureContext<'a>,
+        images: &[RgbaImage],
+        settings: &TextureSettings,
+    ) -> Result<Self, TextureError> {
+        let max_size = context.device.limits().max_texture_dimension_2d;
+
+        let area: u64 = images
+            .iter()
+            .map(|img| (img.width() + PADDING) as u64 * (img.height() + PADDING) as u64)
+            .sum();
+        let widest = images.iter().map(|img| img.width()).max().unwrap_or(0);
+        let width = ((area as f64).sqrt().ceil() as u32)
+            .max(widest)
+            .next_power_of_two();
+
+        // Tallest images first, so each shelf wastes little height.
+        let mut order = (0..images.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| std::cmp::Reverse(images[i].height()));
+
+        let mut positions = vec![[0, 0]; images.len()];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for &i in &order {
+            let (w, h) = images[i].dimensions();
+            if x + w > width {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+            positions[i] = [x, y];
+            x += w + PADDING;
+            shelf_height = shelf_height.max(h + PADDING);
+        }
+        let height = y + shelf_height;
+        if width > max_size || height > max_size {
+            return Err(TextureError::InvalidSize([width, height]));
+        }
+
+        let mut atlas = RgbaImage::new(width, height);
+        for (img, &[x, y]) in images.iter().zip(&positions) {
+            atlas
+                .copy_from(img, x, y)
+                .map_err(TextureError::ImageError)?;
+        }
+        let texture = Texture::from_image(context, &atlas, settings)?;
+
+        let uv_rects = images
+            .iter()
+            .zip(&positions)
+            .map(|(img, &[x, y])| {
+                [
+                    x as f32 / width as f32,
+                    y as f32 / height as f32,
+                    img.width() as f32 / width as f32,
+                    img.height() as f32 / height as f32,
+                ]
+            })
+            .collect();
+
+        Ok(TextureAtlas { texture, uv_rects })
+    }
+
+    /// Returns the texture holding the packed images.
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Returns the normalized `[x, y, width, height]` rectangles of the packed images, in input order.
+    pub fn uv_rects(&self) -> &[[f32; 4]] {
+        &self.uv_rects
+    }
+
+    /// Returns the texture and the rectangles of the packed images.
+    pub fn into_parts(self) -> (Texture, Vec<[f32; 4]>) {
+        (self.texture, self.uv_rects)
+    }
+}
@@ -0,0 +1,270 @@
+//! Glyphs of several fonts sharing one atlas texture, so text mixing fonts is drawn in one batch.
+
+use crate::{
+    quad, transform_position, Texture, TextureContext, TextureError, TexturedPipelineInput,
+    WgpuGraphics,
+};
+use graphics::{
+    types::{Color, Matrix2d},
+    DrawState,
+};
+use std::{collections::HashMap, io, path::Path};
+use texture::TextureSettings;
+
+/// Width and height of the glyph atlas texture.
+const ATLAS_SIZE: u32 = 1024;
+/// Empty pixels between glyphs in the atlas, so filtering doesn't bleed into neighbours.
+const PADDING: u32 = 1;
+
+/// A glyph quad, as a `[x, y, width, height]` rectangle and a normalized rectangle in the atlas.
+type GlyphQuad = ([f32; 4], [f32; 4]);
+
+/// A font added to a [`FontSet`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FontId(usize);
+
+/// A piece of text drawn with one font, size and color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextRun<'b> {
+    /// Font of the glyphs, from the [`FontSet`] drawing the text.
+    pub font: FontId,
+    /// Font size, in pixels.
+    pub font_size: f32,
+    /// Color of the glyphs.
+    pub color: Color,
+    /// Text of the run.
+    pub text: &'b str,
+}
+
+/// A glyph loaded into a [`FontSet`].
+#[derive(Debug, Clone, Copy)]
+struct Glyph {
+    advance_width: f32,
+    /// `[x, y, width, height]` relative to the pen position, and normalized rectangle in the atlas,
+    /// or `None` for glyphs without outline, such as spaces.
+    quad: Option<([f32; 4], [f32; 4])>,
+}
+
+/// Stores glyphs of several fonts and sizes for text rendering, in a single RGBA texture.
+///
+/// Unlike with one [`GlyphCache`](`crate::GlyphCache`) per font, switching fonts doesn't switch textures,
+/// so [`WgpuGraphics::draw_text_runs`](`crate::WgpuGraphics::draw_text_runs`) draws styled text in one draw call.
+pub struct FontSet<'a> {
+    /// The factory used to create and update the atlas texture.
+    pub factory: TextureContext<'a>,
+    fonts: Vec<rusttype::Font<'static>>,
+    texture: Texture,
+    glyphs: HashMap<(FontId, u32, char), Glyph>,
+    cursor: [u32; 2],
+    shelf_height: u32,
+}
+
+impl<'a> FontSet<'a> {
+    /// Creates a new `FontSet` without fonts, sampling its atlas with `settings`.
+    ///
+    /// Border colors wgpu doesn't support are approximated with the nearest of transparent black, opaque black and opaque white.
+    pub fn new(mut factory: TextureContext<'a>, settings: &TextureSettings) -> Self {
+        let texture = factory.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Font Set Glyph Texture"),
+            size: wgpu::Extent3d {
+                width: ATLAS_SIZE,
+                height: ATLAS_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: factory.texture_format(),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let texture = Texture::from_wgpu(
+            factory.device,
+            texture,
+            [ATLAS_SIZE, ATLAS_SIZE],
+            factory.sampler(settings),
+        );
+        FontSet {
+            factory,
+            fonts: vec![],
+            texture,
+            glyphs: HashMap::new(),
+            cursor: [0, 0],
+            shelf_height: 0,
+        }
+    }
+
+    /// Adds the font at `font`.
+    pub fn add_font<P>(&mut self, font: P) -> io::Result<FontId>
+    where
+        P: AsRef<Path>,
+    {
+        let data = std::fs::read(font)?;
+        self.add_font_from_bytes(&data)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid font data"))
+    }
+
+    /// Adds the font in `font`, or returns `None` if it isn't a valid font.
+    pub fn add_font_from_bytes(&mut self, font: &[u8]) -> Option<FontId> {
+        let font = rusttype::Font::try_from_vec(font.to_vec())?;
+        self.fonts.push(font);
+        Some(FontId(self.fonts.len() - 1))
+    }
+
+    /// Returns the atlas texture, whose alpha channel holds the glyph coverage.
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Returns the quads of the glyphs of each of `runs`, laid out one after the other,
+    /// as pairs of `[x, y, width, height]` rectangles, relative to the start of the baseline,
+    /// and normalized rectangles in the atlas.
+    ///
+    /// Glyphs are loaded into the atlas on first use, for each font size.
+    /// Returns an error if the atlas is full.
+    /// Panics if the font of a run wasn't added to this set.
+    pub fn layout(&mut self, runs: &[TextRun]) -> Result<Vec<Vec<GlyphQuad>>, TextureError> {
+        let mut x = 0.0;
+        let mut run_quads = Vec::with_capacity(runs.len());
+        for run in runs {
+            let mut quads = Vec::with_capacity(run.text.len());
+            for ch in run.text.chars() {
+                let glyph = self.glyph(run.font, run.font_size, ch)?;
+                if let Some(([gx, gy, w, h], uv_rect)) = glyph.quad {
+                    quads.push(([x + gx, gy, w, h], uv_rect));
+                }
+                x += glyph.advance_width;
+            }
+            run_quads.push(quads);
+        }
+        Ok(run_quads)
+    }
+
+    /// Returns the glyph of `ch` in `font` at `font_size`, loading it into the atlas if needed.
+    fn glyph(&mut self, font: FontId, font_size: f32, ch: char) -> Result<Glyph, TextureError> {
+        let key = (font, font_size.to_bits(), ch);
+        if let Some(&glyph) = self.glyphs.get(&key) {
+            return Ok(glyph);
+        }
+
+        let glyph = self.fonts[font.0]
+            .glyph(ch)
+            .scaled(rusttype::Scale::uniform(font_size));
+        let advance_width = glyph.h_metrics().advance_width;
+        let glyph = glyph.positioned(rusttype::point(0.0, 0.0));
+        let quad = match glyph.pixel_bounding_box() {
+            Some(bounds) => {
+                let size = [bounds.width() as u32, bounds.height() as u32];
+                let mut pixels = vec![255; (4 * size[0] * size[1]) as usize];
+                glyph.draw(|x, y, v| {
+                    pixels[(4 * (y * size[0] + x) + 3) as usize] = (v * 255.0).round() as u8
+                });
+                let uv_rect = self.upload(&pixels, size)?;
+                Some((
+                    [
+                        bounds.min.x as f32,
+                        bounds.min.y as f32,
+                        size[0] as f32,
+                        size[1] as f32,
+                    ],
+                    uv_rect,
+                ))
+            }
+            None => None,
+        };
+
+        let glyph = Glyph {
+            advance_width,
+            quad,
+        };
+        self.glyphs.insert(key, glyph);
+        Ok(glyph)
+    }
+
+    /// Uploads the RGBA `pixels` of a glyph of `size` into the next free region of the atlas,
+    /// and returns its normalized rectangle in the atlas.
+    fn upload(
+        &mut self,
+        pixels: &[u8],
+        [width, height]: [u32; 2],
+    ) -> Result<[f32; 4], TextureError> {
+        let size = [width + PADDING, height + PADDING];
+        if self.cursor[0] + size[0] > ATLAS_SIZE {
+            self.cursor = [0, self.cursor[1] + self.shelf_height];
+            self.shelf_height = 0;
+        }
+        if self.cursor[0] + size[0] > ATLAS_SIZE || self.cursor[1] + size[1] > ATLAS_SIZE {
+            return Err(TextureError::OutOfBounds {
+                offset: self.cursor,
+                size,
+            });
+        }
+        let [x, y] = self.cursor;
+        self.cursor[0] += size[0];
+        self.shelf_height = self.shelf_height.max(size[1]);
+
+        self.factory.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let atlas_size = ATLAS_SIZE as f32;
+        Ok([
+            x as f32 / atlas_size,
+            y as f32 / atlas_size,
+            width as f32 / atlas_size,
+            height as f32 / atlas_size,
+        ])
+    }
+}
+
+impl<'a> WgpuGraphics<'a> {
+    /// Draws `runs` one after the other with glyphs of `fonts`, starting at the origin of `transform` on the baseline.
+    ///
+    /// All the glyphs are in the atlas of `fonts`, so the runs are drawn in one draw call, whatever their fonts.
+    /// Returns an error if the glyphs don't fit in the atlas of `fonts`.
+    pub fn draw_text_runs(
+        &mut self,
+        draw_state: &DrawState,
+        fonts: &mut FontSet,
+        runs: &[TextRun],
+        transform: Matrix2d,
+    ) -> Result<(), TextureError> {
+        let run_quads = fonts.layout(runs)?;
+        let alpha_test = self.alpha_test.unwrap_or(0.0);
+        let mut textured_inputs = Vec::new();
+        for (run, quads) in runs.iter().zip(run_quads) {
+            let vertex = |x: f32, y: f32, uv: [f32; 2]| TexturedPipelineInput {
+                xy: transform_position(transform, [x as f64, y as f64]),
+                uv,
+                color: run.color,
+                alpha_test,
+            };
+            for ([x, y, w, h], [u, v, uw, vh]) in quads {
+                textured_inputs.extend_from_slice(&quad(
+                    vertex(x, y, [u, v]),
+                    vertex(x + w, y, [u + uw, v]),
+                    vertex(x + w, y + h, [u + uw, v + vh]),
+                    vertex(x, y + h, [u, v + vh]),
+                ));
+            }
+        }
+        self.push_textured(textured_inputs, fonts.texture(), draw_state);
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0)
+@binding(0)
+var texture: texture_2d<f32>;
+@group(0)
+@binding(1)
+var s_sampler: sampler;
+
+// Draws a triangle covering the whole target.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn luma(color: vec4<f32>) -> f32 {
+    return dot(color.rgb, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+fn sample_at(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(texture, s_sampler, uv, 0.0);
+}
+
+// Blurs along edges, found from the luma of the diagonal neighbours.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let span_max = 8.0;
+    let reduce_mul = 1.0 / 8.0;
+    let reduce_min = 1.0 / 128.0;
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(texture));
+    let center = sample_at(in.uv);
+    let luma_nw = luma(sample_at(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample_at(in.uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample_at(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample_at(in.uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(center);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var dir = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * reduce_mul, reduce_min);
+    let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2<f32>(-span_max), vec2<f32>(span_max)) * texel;
+
+    let color_a = 0.5 * (
+        sample_at(in.uv + dir * (1.0 / 3.0 - 0.5)) + sample_at(in.uv + dir * (2.0 / 3.0 - 0.5))
+    );
+    let color_b = color_a * 0.5 + 0.25 * (sample_at(in.uv - dir * 0.5) + sample_at(in.uv + dir * 0.5));
+    let luma_b = luma(color_b);
+    if luma_b < luma_min || luma_b > luma_max {
+        return vec4<f32>(color_a.rgb, center.a);
+    }
+    return vec4<f32>(color_b.rgb, center.a);
+}
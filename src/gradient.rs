@@ -0,0 +1,220 @@
+//! Gradients computed per pixel by a fragment shader.
+
+use crate::{transform_position, BatchKind, PsoStencil, TexturedPipelineInput, WgpuGraphics};
+use graphics::{
+    draw_state::{Blend, Stencil},
+    types::{Color, Matrix2d},
+    DrawState,
+};
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+/// Maximum number of color stops of a gradient.
+pub const MAX_GRADIENT_STOPS: usize = 8;
+
+/// Shape of a gradient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientKind {
+    /// Goes across the rectangle along `angle`, in radians, where 0 goes from its left side to its right side.
+    Linear {
+        /// Direction of the gradient, in radians.
+        angle: f64,
+    },
+    /// Goes from `center` to `radius` around it, in the coordinates of the rectangle.
+    Radial {
+        /// Center of the gradient, where the first stop is.
+        center: [f64; 2],
+        /// Distance from the center where the last stop is.
+        radius: f64,
+    },
+}
+
+/// Uniforms of the "gradient" shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct GradientParams {
+    /// Linear: `t = dot(uv, params.xy) + params.z`. Radial: center in `xy` and inverse radius in `z`.
+    params: [f32; 4],
+    kind: u32,
+    count: u32,
+    _padding: [u32; 2],
+    offsets: [[f32; 4]; MAX_GRADIENT_STOPS / 4],
+    colors: [Color; MAX_GRADIENT_STOPS],
+}
+
+/// Pipelines drawing gradients with the "textured" vertex layout, where UVs are untransformed positions.
+pub(crate) struct GradientPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipelines: PsoStencil<wgpu::RenderPipeline>,
+}
+
+impl GradientPipelines {
+    /// Creates new `GradientPipelines`, using `f` with a pipeline layout, shader module and fragment entry point,
+    /// for all Stencil and Blend modes.
+    pub(crate) fn new<F>(device: &wgpu::Device, mut f: F) -> Self
+    where
+        F: FnMut(
+            &str,
+            &wgpu::PipelineLayout,
+            &wgpu::ShaderModule,
+            &str,
+            Option<wgpu::BlendState>,
+            wgpu::StencilState,
+        ) -> wgpu::RenderPipeline,
+    {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Gradient Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Gradient Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::include_wgsl!("gradient.wgsl"));
+
+        let pipelines = PsoStencil::new(|blend, stencil| {
+            f(
+                "Gradient Render Pipeline",
+                &pipeline_layout,
+                &shader_module,
+                "fs_main",
+                blend,
+                stencil,
+            )
+        });
+
+        GradientPipelines {
+            bind_group_layout,
+            pipelines,
+        }
+    }
+
+    /// Returns the pipeline for `stencil` and `blend`, and the stencil reference value to draw it with.
+    pub(crate) fn stencil_blend(
+        &self,
+        stencil: Option<Stencil>,
+        blend: Option<Blend>,
+    ) -> (&wgpu::RenderPipeline, Option<u8>) {
+        self.pipelines.stencil_blend(stencil, blend)
+    }
+
+    /// Creates the bind group of a gradient of `kind` through `stops`, filling `rect`.
+    pub(crate) fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        [x, y, w, h]: [f64; 4],
+        kind: GradientKind,
+        stops: &[(f32, Color)],
+    ) -> Arc<wgpu::BindGroup> {
+        let (kind, params) = match kind {
+            GradientKind::Linear { angle } => {
+                let (dy, dx) = angle.sin_cos();
+                let [mid_x, mid_y] = [x + w / 2.0, y + h / 2.0];
+                // Distance from the middle to the side the gradient starts from, along its direction.
+                let extent = 0.5 * (dx.abs() * w + dy.abs() * h);
+                let scale = if extent > 0.0 { 0.5 / extent } else { 0.0 };
+                (
+                    0,
+                    [
+                        (dx * scale) as f32,
+                        (dy * scale) as f32,
+                        (0.5 - (mid_x * dx + mid_y * dy) * scale) as f32,
+                        0.0,
+                    ],
+                )
+            }
+            GradientKind::Radial { center, radius } => (
+                1,
+                [
+                    center[0] as f32,
+                    center[1] as f32,
+                    (1.0 / radius.max(f64::EPSILON)) as f32,
+                    0.0,
+                ],
+            ),
+        };
+
+        let stops = &stops[..stops.len().min(MAX_GRADIENT_STOPS)];
+        let mut offsets = [[0.0; 4]; MAX_GRADIENT_STOPS / 4];
+        let mut colors = [[0.0; 4]; MAX_GRADIENT_STOPS];
+        for (i, &(offset, color)) in stops.iter().enumerate() {
+            offsets[i / 4][i % 4] = offset;
+            colors[i] = color;
+        }
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gradient Params Buffer"),
+            contents: bytemuck::bytes_of(&GradientParams {
+                params,
+                kind,
+                count: stops.len() as u32,
+                _padding: [0; 2],
+                offsets,
+                colors,
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        Arc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Gradient Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            }],
+        }))
+    }
+}
+
+impl<'a> WgpuGraphics<'a> {
+    /// Fills `rect`, transformed by `transform`, with a gradient of `kind` through `stops`.
+    ///
+    /// The gradient is computed per pixel, so radial gradients stay smooth, unlike with per-vertex colors.
+    /// `stops` are pairs of an offset from 0 to 1 and a color, sorted by offset.
+    /// Only the first [`MAX_GRADIENT_STOPS`] stops are used, and nothing is drawn without stops.
+    pub fn gradient_rect(
+        &mut self,
+        draw_state: &DrawState,
+        rect: [f64; 4],
+        transform: Matrix2d,
+        kind: GradientKind,
+        stops: &[(f32, Color)],
+    ) {
+        if stops.is_empty() {
+            return;
+        }
+        let bind_group =
+            self.wgpu2d
+                .gradient_pipelines
+                .bind_group(self.wgpu2d.device, rect, kind, stops);
+
+        let [x, y, w, h] = rect;
+        let corner = |cx: f64, cy: f64| TexturedPipelineInput {
+            xy: transform_position(transform, [cx, cy]),
+            uv: [cx as f32, cy as f32],
+            color: [1.0; 4],
+            alpha_test: 0.0,
+        };
+        self.push_quad(
+            BatchKind::Gradient(bind_group),
+            draw_state,
+            [
+                corner(x, y),
+                corner(x + w, y),
+                corner(x + w, y + h),
+                corner(x, y + h),
+            ],
+        );
+    }
+}
@@ -0,0 +1,57 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+struct Gradient {
+    params: vec4<f32>,
+    kind: u32,
+    count: u32,
+    offsets: array<vec4<f32>, 2>,
+    colors: array<vec4<f32>, 8>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> gradient: Gradient;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+    out.uv = in.uv;
+    return out;
+}
+
+fn offset(i: u32) -> f32 {
+    return gradient.offsets[i / 4u][i % 4u];
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var t: f32;
+    if gradient.kind == 0u {
+        t = dot(in.uv, gradient.params.xy) + gradient.params.z;
+    } else {
+        t = length(in.uv - gradient.params.xy) * gradient.params.z;
+    }
+    t = clamp(t, 0.0, 1.0);
+
+    if t <= offset(0u) {
+        return gradient.colors[0];
+    }
+    for (var i = 1u; i < gradient.count; i = i + 1u) {
+        let end = offset(i);
+        if t <= end {
+            let start = offset(i - 1u);
+            let s = (t - start) / max(end - start, 0.00001);
+            return mix(gradient.colors[i - 1u], gradient.colors[i], s);
+        }
+    }
+    return gradient.colors[gradient.count - 1u];
+}
@@ -0,0 +1,82 @@
+//! Sub-scenes rendered once into a texture, and redrawn from it until they change.
+
+use crate::{Texture, TextureError, Wgpu2d, WgpuGraphics};
+use graphics::{
+    types::{Color, Matrix2d},
+    Context, DrawState, Graphics, Image,
+};
+use texture::TextureSettings;
+
+/// A render target caching a sub-scene, which is only rendered again when marked dirty.
+///
+/// This cuts the per-frame cost of complex but mostly static content, such as UI panels.
+pub struct CachedLayer {
+    texture: Texture,
+    clear_color: Color,
+    dirty: bool,
+}
+
+impl CachedLayer {
+    /// Creates a new dirty `CachedLayer` of `size`, cleared to `clear_color` before each render.
+    ///
+    /// The layer is drawn with alpha blending, which darkens its semi-transparent pixels
+    /// if `clear_color` is transparent, so it works best with an opaque clear color or hard edges.
+    pub fn new(
+        wgpu2d: &Wgpu2d,
+        device: &wgpu::Device,
+        size: [u32; 2],
+        clear_color: Color,
+        settings: &TextureSettings,
+    ) -> Self {
+        CachedLayer {
+            texture: wgpu2d.create_render_target(device, size, settings),
+            clear_color,
+            dirty: true,
+        }
+    }
+
+    /// Marks the sub-scene as changed, so the next [`CachedLayer::update`] renders it again.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Returns `true` if the next [`CachedLayer::update`] renders the sub-scene.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns the texture holding the rendered sub-scene.
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Renders the sub-scene with `f` if the layer is dirty, and returns the encoded commands.
+    ///
+    /// Returns `None` if the layer is up to date, or an error if `wgpu2d` renders into another format than the layer.
+    /// The commands must be submitted before those of the frame drawing the layer, in an earlier or the same submission.
+    pub fn update<F>(
+        &mut self,
+        wgpu2d: &Wgpu2d,
+        device: &wgpu::Device,
+        f: F,
+    ) -> Result<Option<wgpu::CommandBuffer>, TextureError>
+    where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        if !self.dirty {
+            return Ok(None);
+        }
+        let clear_color = self.clear_color;
+        let command_buffer = wgpu2d.draw_to_texture(device, &self.texture, |c, g| {
+            g.clear_color(clear_color);
+            f(c, g);
+        })?;
+        self.dirty = false;
+        Ok(Some(command_buffer))
+    }
+
+    /// Draws the rendered sub-scene, with its top left corner at the origin of `transform`.
+    pub fn draw(&self, g: &mut WgpuGraphics, transform: Matrix2d) {
+        Image::new().draw(&self.texture, &DrawState::default(), transform, g);
+    }
+}
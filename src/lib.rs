@@ -2,19 +2,65 @@
 
 use graphics::{
     draw_state::{Blend, Stencil},
-    types::Color,
+    types::{Color, Matrix2d},
     Context, DrawState, Graphics, Viewport,
 };
 use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
     fmt::{self, Display, Formatter},
+    future::Future,
+    ops::Range,
     path::Path,
+    sync::Arc,
+    task::{Context as TaskContext, Poll, Wake, Waker},
 };
 use wgpu::util::DeviceExt;
 
+pub use app::App2d;
+pub use atlas::TextureAtlas;
+pub use font_set::{FontId, FontSet, TextRun};
+pub use gradient::{GradientKind, MAX_GRADIENT_STOPS};
 pub use graphics::ImageSize;
+pub use layer::CachedLayer;
+pub use loader::TextureLoader;
+pub use multitexture::MultitextureMode;
+pub use path::FillRule;
+pub use sdf::SdfGlyphCache;
 pub use texture::*;
+pub use texture_array::TextureArray;
+pub use timing::PassTiming;
+
+use arc::ArcPipelines;
+use fxaa::FxaaPipeline;
+use gradient::GradientPipelines;
+use multitexture::{MultitexturePipelineInput, MultitexturePipelines};
+use path::PathPipelines;
+use shadow::ShadowPipelines;
+use staging::StagedUploads;
+use texture_array::TextureArrayPipelines;
+use timing::GpuTimer;
+
+mod app;
+mod arc;
+mod atlas;
+mod font_set;
+mod fxaa;
+mod gradient;
+mod layer;
+mod loader;
+mod multitexture;
+mod path;
+mod sdf;
+mod shadow;
+mod staging;
+mod texture_array;
+mod timing;
 
 /// Stores textures for text rendering.
+///
+/// Glyphs which fail to upload make text drawing return an error,
+/// and are counted by [`TextureContext::failed_uploads`] of the cache's `factory`.
 pub type GlyphCache<'a> =
     graphics::glyph_cache::rusttype::GlyphCache<'a, TextureContext<'a>, Texture>;
 
@@ -54,6 +100,11 @@ struct TexturedPipelineInput {
     xy: [f32; 2],
     uv: [f32; 2],
     color: [f32; 4],
+    /// Alpha below which fragments are discarded, or 0 to keep them all.
+    ///
+    /// The "sdf" shader uses it as the distance glyph edges are at instead.
+    /// The "texture array" shader uses it as the layer to sample instead.
+    alpha_test: f32,
 }
 
 impl TexturedPipelineInput {
@@ -77,6 +128,11 @@ impl TexturedPipelineInput {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x4,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32,
+                },
             ],
         }
     }
@@ -93,6 +149,72 @@ struct PsoBlend<T> {
 }
 
 impl<T> PsoBlend<T> {
+    /// Creates a new `PsoBlend<T>`, using `f`, for all Blend mode.
+    fn new<F>(mut f: F) -> PsoBlend<T>
+    where
+        F: FnMut(Option<wgpu::BlendState>) -> T,
+    {
+        use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
+
+        let blend_add = BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+        };
+        let blend_lighter = BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::SrcAlpha,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent {
+                src_factor: BlendFactor::Zero,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+        };
+        let blend_multiply = BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::Dst,
+                dst_factor: BlendFactor::Zero,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent {
+                src_factor: BlendFactor::DstAlpha,
+                dst_factor: BlendFactor::Zero,
+                operation: BlendOperation::Add,
+            },
+        };
+        let blend_invert = BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::Constant,
+                dst_factor: BlendFactor::Src,
+                operation: BlendOperation::Subtract,
+            },
+            alpha: BlendComponent {
+                src_factor: BlendFactor::Zero,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+        };
+
+        PsoBlend {
+            none: f(None),
+            alpha: f(Some(BlendState::ALPHA_BLENDING)),
+            add: f(Some(blend_add)),
+            lighter: f(Some(blend_lighter)),
+            multiply: f(Some(blend_multiply)),
+            invert: f(Some(blend_invert)),
+        }
+    }
+
     /// Returns `T` object for `blend`.
     fn blend(&self, blend: Option<Blend>) -> &T {
         match blend {
@@ -106,25 +228,22 @@ impl<T> PsoBlend<T> {
     }
 }
 
-/// Stores `T` object for each (Stencil, Blend) mode.
-struct PsoStencil<T> {
-    none: PsoBlend<T>,
-    clip: PsoBlend<T>,
-    inside: PsoBlend<T>,
-    outside: PsoBlend<T>,
-    increment: PsoBlend<T>,
+/// Stores `T` object for each Stencil mode.
+struct PsoStencilOnly<T> {
+    none: T,
+    clip: T,
+    inside: T,
+    outside: T,
+    increment: T,
 }
 
-impl<T> PsoStencil<T> {
-    /// Creates a new `PsoStencil<T>`, using `f`, for all (Stencil, Blend) mode.
-    fn new<F>(mut f: F) -> PsoStencil<T>
+impl<T> PsoStencilOnly<T> {
+    /// Creates a new `PsoStencilOnly<T>`, using `f`, for all Stencil mode.
+    fn new<F>(mut f: F) -> PsoStencilOnly<T>
     where
-        F: FnMut(Option<wgpu::BlendState>, wgpu::StencilState) -> T,
+        F: FnMut(wgpu::StencilState) -> T,
     {
-        use wgpu::{
-            BlendComponent, BlendFactor, BlendOperation, BlendState, CompareFunction,
-            StencilFaceState, StencilOperation, StencilState,
-        };
+        use wgpu::{CompareFunction, StencilFaceState, StencilOperation, StencilState};
 
         let stencil_none = StencilState {
             front: StencilFaceState::IGNORE,
@@ -146,6 +265,8 @@ impl<T> PsoStencil<T> {
             read_mask: 255,
             write_mask: 255,
         };
+        // Drawing inside or outside a clip only reads the stencil, so overlapping draws
+        // in the same clip region all compare against the value the clip wrote.
         let stencil_inside = StencilState {
             front: StencilFaceState {
                 compare: CompareFunction::Equal,
@@ -156,7 +277,7 @@ impl<T> PsoStencil<T> {
                 ..Default::default()
             },
             read_mask: 255,
-            write_mask: 255,
+            write_mask: 0,
         };
         let stencil_outside = StencilState {
             front: StencilFaceState {
@@ -168,7 +289,7 @@ impl<T> PsoStencil<T> {
                 ..Default::default()
             },
             read_mask: 255,
-            write_mask: 255,
+            write_mask: 0,
         };
         let stencil_increment = StencilState {
             front: StencilFaceState {
@@ -185,129 +306,234 @@ impl<T> PsoStencil<T> {
             write_mask: 255,
         };
 
-        let blend_add = BlendState {
-            color: BlendComponent {
-                src_factor: BlendFactor::One,
-                dst_factor: BlendFactor::One,
-                operation: BlendOperation::Add,
-            },
-            alpha: BlendComponent {
-                src_factor: BlendFactor::One,
-                dst_factor: BlendFactor::One,
-                operation: BlendOperation::Add,
-            },
-        };
-        let blend_lighter = BlendState {
-            color: BlendComponent {
-                src_factor: BlendFactor::SrcAlpha,
-                dst_factor: BlendFactor::One,
-                operation: BlendOperation::Add,
-            },
-            alpha: BlendComponent {
-                src_factor: BlendFactor::Zero,
-                dst_factor: BlendFactor::One,
-                operation: BlendOperation::Add,
-            },
-        };
-        let blend_multiply = BlendState {
-            color: BlendComponent {
-                src_factor: BlendFactor::Dst,
-                dst_factor: BlendFactor::Zero,
-                operation: BlendOperation::Add,
-            },
-            alpha: BlendComponent {
-                src_factor: BlendFactor::DstAlpha,
-                dst_factor: BlendFactor::Zero,
-                operation: BlendOperation::Add,
-            },
-        };
-        let blend_invert = BlendState {
-            color: BlendComponent {
-                src_factor: BlendFactor::Constant,
-                dst_factor: BlendFactor::Src,
-                operation: BlendOperation::Subtract,
-            },
-            alpha: BlendComponent {
-                src_factor: BlendFactor::Zero,
-                dst_factor: BlendFactor::One,
-                operation: BlendOperation::Add,
-            },
-        };
+        PsoStencilOnly {
+            none: f(stencil_none),
+            clip: f(stencil_clip),
+            inside: f(stencil_inside),
+            outside: f(stencil_outside),
+            increment: f(stencil_increment),
+        }
+    }
+
+    /// Returns `T` object for `stencil`, and the stencil reference value to draw it with.
+    fn stencil(&self, stencil: Option<Stencil>) -> (&T, Option<u8>) {
+        match stencil {
+            None => (&self.none, None),
+            Some(Stencil::Clip(val)) => (&self.clip, Some(val)),
+            Some(Stencil::Inside(val)) => (&self.inside, Some(val)),
+            Some(Stencil::Outside(val)) => (&self.outside, Some(val)),
+            Some(Stencil::Increment) => (&self.increment, None),
+        }
+    }
+}
+
+/// Stores `T` object for each (Stencil, Blend) mode.
+struct PsoStencil<T> {
+    stencils: PsoStencilOnly<PsoBlend<T>>,
+}
 
+impl<T> PsoStencil<T> {
+    /// Creates a new `PsoStencil<T>`, using `f`, for all (Stencil, Blend) mode.
+    fn new<F>(mut f: F) -> PsoStencil<T>
+    where
+        F: FnMut(Option<wgpu::BlendState>, wgpu::StencilState) -> T,
+    {
         PsoStencil {
-            none: PsoBlend {
-                none: f(None, stencil_none.clone()),
-                alpha: f(Some(BlendState::ALPHA_BLENDING), stencil_none.clone()),
-                add: f(Some(blend_add), stencil_none.clone()),
-                lighter: f(Some(blend_lighter), stencil_none.clone()),
-                multiply: f(Some(blend_multiply), stencil_none.clone()),
-                invert: f(Some(blend_invert), stencil_none),
-            },
-            clip: PsoBlend {
-                none: f(None, stencil_clip.clone()),
-                alpha: f(Some(BlendState::ALPHA_BLENDING), stencil_clip.clone()),
-                add: f(Some(blend_add), stencil_clip.clone()),
-                lighter: f(Some(blend_lighter), stencil_clip.clone()),
-                multiply: f(Some(blend_multiply), stencil_clip.clone()),
-                invert: f(Some(blend_invert), stencil_clip),
-            },
-            inside: PsoBlend {
-                none: f(None, stencil_inside.clone()),
-                alpha: f(Some(BlendState::ALPHA_BLENDING), stencil_inside.clone()),
-                add: f(Some(blend_add), stencil_inside.clone()),
-                lighter: f(Some(blend_lighter), stencil_inside.clone()),
-                multiply: f(Some(blend_multiply), stencil_inside.clone()),
-                invert: f(Some(blend_invert), stencil_inside),
-            },
-            outside: PsoBlend {
-                none: f(None, stencil_outside.clone()),
-                alpha: f(Some(BlendState::ALPHA_BLENDING), stencil_outside.clone()),
-                add: f(Some(blend_add), stencil_outside.clone()),
-                lighter: f(Some(blend_lighter), stencil_outside.clone()),
-                multiply: f(Some(blend_multiply), stencil_outside.clone()),
-                invert: f(Some(blend_invert), stencil_outside),
-            },
-            increment: PsoBlend {
-                none: f(None, stencil_increment.clone()),
-                alpha: f(Some(BlendState::ALPHA_BLENDING), stencil_increment.clone()),
-                add: f(Some(blend_add), stencil_increment.clone()),
-                lighter: f(Some(blend_lighter), stencil_increment.clone()),
-                multiply: f(Some(blend_multiply), stencil_increment.clone()),
-                invert: f(Some(blend_invert), stencil_increment),
-            },
+            stencils: PsoStencilOnly::new(|stencil| {
+                PsoBlend::new(|blend| f(blend, stencil.clone()))
+            }),
         }
     }
 
     /// Returns `T` object for `stencil` and `blend`.
     fn stencil_blend(&self, stencil: Option<Stencil>, blend: Option<Blend>) -> (&T, Option<u8>) {
-        match stencil {
-            None => (self.none.blend(blend), None),
-            Some(Stencil::Clip(val)) => (self.clip.blend(blend), Some(val)),
-            Some(Stencil::Inside(val)) => (self.inside.blend(blend), Some(val)),
-            Some(Stencil::Outside(val)) => (self.outside.blend(blend), Some(val)),
-            Some(Stencil::Increment) => (self.increment.blend(blend), None),
-        }
+        let (pso_blend, stencil_val) = self.stencils.stencil(stencil);
+        (pso_blend.blend(blend), stencil_val)
     }
 }
 
 /// Represents a texture.
 pub struct Texture {
     texture: wgpu::Texture,
-    bind_group: wgpu::BindGroup,
+    view: wgpu::TextureView,
+    sampler: Arc<wgpu::Sampler>,
+    bind_group: Arc<wgpu::BindGroup>,
+    usage: wgpu::TextureUsages,
     width: u32,
     height: u32,
 }
 
+/// Highest anisotropy of texture samplers, the most wgpu allows.
+pub const MAX_ANISOTROPY: u16 = 16;
+
 /// Context required to create and update textures.
 pub struct TextureContext<'a> {
     device: &'a wgpu::Device,
     queue: &'a wgpu::Queue,
+    failed_uploads: usize,
+    staged_uploads: Option<StagedUploads>,
+    anisotropy: u16,
+    /// Whether textures are created in an sRGB format, decoded to linear when sampled.
+    srgb: bool,
+    /// Samplers shared by the textures created with the same sampling settings.
+    samplers: HashMap<SamplerKey, Arc<wgpu::Sampler>>,
 }
 
+/// Address modes, filters, anisotropy and border color of a sampler, which textures sampled alike share.
+type SamplerKey = (
+    [wgpu::AddressMode; 2],
+    [wgpu::FilterMode; 3],
+    u16,
+    Option<wgpu::SamplerBorderColor>,
+);
+
 impl<'a> TextureContext<'a> {
     /// Creates a new `TextureContext` from its parts.
     pub fn from_parts(device: &'a wgpu::Device, queue: &'a wgpu::Queue) -> Self {
-        TextureContext { device, queue }
+        TextureContext {
+            device,
+            queue,
+            failed_uploads: 0,
+            staged_uploads: None,
+            anisotropy: 1,
+            srgb: true,
+            samplers: HashMap::new(),
+        }
+    }
+
+    /// Creates a new `TextureContext` uploading texture updates through a staging belt,
+    /// which allocates staging memory by chunks of `chunk_size` bytes and reuses it every frame.
+    ///
+    /// This suits textures updated every frame, such as video frames or live canvases.
+    /// Updates are only recorded, and uploaded by [`TextureContext::finish_frame`].
+    /// Texture creations are uploaded right away, as with [`TextureContext::from_parts`].
+    pub fn with_staging_belt(
+        device: &'a wgpu::Device,
+        queue: &'a wgpu::Queue,
+        chunk_size: wgpu::BufferAddress,
+    ) -> Self {
+        TextureContext {
+            staged_uploads: Some(StagedUploads::new(device, chunk_size)),
+            ..TextureContext::from_parts(device, queue)
+        }
+    }
+
+    /// Uploads the texture updates recorded since the last call, and recalls the staging memory for the next frame.
+    ///
+    /// Must be called once per frame, before submitting commands drawing the updated textures.
+    /// It does nothing unless the context was created with [`TextureContext::with_staging_belt`].
+    pub fn finish_frame(&mut self) {
+        if let Some(staged_uploads) = &mut self.staged_uploads {
+            staged_uploads.finish(self.queue);
+        }
+    }
+
+    /// Sets the maximum anisotropy of the samplers of textures created from now on, 1 by default.
+    ///
+    /// Anisotropic filtering keeps minified or obliquely drawn textures sharp.
+    /// It only applies to textures whose [`TextureSettings`] filters are all linear, including the mipmap filter,
+    /// and is clamped from 1 to [`MAX_ANISOTROPY`]. Backends without anisotropic filtering ignore it.
+    pub fn set_anisotropy(&mut self, anisotropy: u16) {
+        self.anisotropy = anisotropy.clamp(1, MAX_ANISOTROPY);
+    }
+
+    /// Returns the maximum anisotropy of the samplers of created textures.
+    pub fn anisotropy(&self) -> u16 {
+        self.anisotropy
+    }
+
+    /// Returns the sampler of textures with `settings`, created on first use and shared by the following textures sampled alike.
+    pub(crate) fn sampler(&mut self, settings: &TextureSettings) -> Arc<wgpu::Sampler> {
+        let descriptor = Texture::sampler_descriptor(settings, self.anisotropy);
+        let key = (
+            [descriptor.address_mode_u, descriptor.address_mode_v],
+            [
+                descriptor.mag_filter,
+                descriptor.min_filter,
+                descriptor.mipmap_filter,
+            ],
+            descriptor.anisotropy_clamp,
+            descriptor.border_color,
+        );
+        let device = self.device;
+        self.samplers
+            .entry(key)
+            .or_insert_with(|| Arc::new(device.create_sampler(&descriptor)))
+            .clone()
+    }
+
+    /// Sets the format of the render targets textures created from now on are drawn onto, such as [`Wgpu2d::format`].
+    ///
+    /// Texture data is taken as sRGB, like the colors of `graphics`, and the texels are written as is
+    /// to targets of a linear 8-bit or 10-bit format such as `Rgba8Unorm`, like vertex colors.
+    /// To an sRGB target, texels are decoded to linear when sampled and encoded back when written,
+    /// so they also come out as is, but blend in linear space. To a float target such as `Rgba16Float`,
+    /// which holds linear colors, texels are decoded to linear. Textures are created for sRGB targets by default.
+    pub fn set_target_format(&mut self, format: wgpu::TextureFormat) {
+        self.srgb = format.is_srgb()
+            || matches!(
+                format,
+                wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba32Float
+            );
+    }
+
+    /// Returns the format of the textures created by this context.
+    fn texture_format(&self) -> wgpu::TextureFormat {
+        if self.srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        }
+    }
+
+    /// Returns how many texture creations and updates have failed with this context.
+    ///
+    /// Failures are also returned as errors, but those are easily dropped on the way,
+    /// e.g. by glyphs which couldn't be cached while drawing text.
+    pub fn failed_uploads(&self) -> usize {
+        self.failed_uploads
+    }
+
+    /// Checks that `len` bytes of RGBA data covering `size`, with rows every `bytes_per_row` bytes,
+    /// can be uploaded at `offset` into a texture of `texture_size`, counting the upload as failed if they can't.
+    fn check_upload(
+        &mut self,
+        texture_size: [u32; 2],
+        offset: [u32; 2],
+        size: [u32; 2],
+        bytes_per_row: usize,
+        len: usize,
+    ) -> Result<(), TextureError> {
+        let max_size = self.device.limits().max_texture_dimension_2d;
+        let fits = |i: usize| {
+            offset[i]
+                .checked_add(size[i])
+                .is_some_and(|end| end <= texture_size[i])
+        };
+        let row_size = 4 * size[0] as usize;
+        let expected = match size[1] {
+            0 => 0,
+            height => bytes_per_row * (height as usize - 1) + row_size,
+        };
+
+        let result = if texture_size.iter().any(|&x| x == 0 || x > max_size) {
+            Err(TextureError::InvalidSize(texture_size))
+        } else if !fits(0) || !fits(1) {
+            Err(TextureError::OutOfBounds { offset, size })
+        } else if bytes_per_row < row_size {
+            Err(TextureError::InvalidStride(bytes_per_row))
+        } else if len < expected {
+            Err(TextureError::DataTooShort {
+                expected,
+                actual: len,
+            })
+        } else {
+            Ok(())
+        };
+        if result.is_err() {
+            self.failed_uploads += 1;
+        }
+        result
     }
 }
 
@@ -330,110 +556,343 @@ impl Texture {
         Texture::from_image(context, &img, settings)
     }
 
-    /// Creates a `Texture` with `img`.
-    pub fn from_image<'a>(
+    /// Creates a `Texture` with image decoding from `bytes`, in any format the `image` crate detects.
+    ///
+    /// This suits images embedded with `include_bytes!`, where reading files is awkward, as on the web.
+    pub fn from_memory<'a>(
         context: &mut TextureContext<'a>,
-        img: &image::RgbaImage,
+        bytes: &[u8],
         settings: &TextureSettings,
     ) -> Result<Self, TextureError> {
-        let (width, height) = img.dimensions();
-        CreateTexture::create(context, Format::Rgba8, img, [width, height], settings)
-    }
+        let img = image::load_from_memory(bytes).map_err(TextureError::ImageError)?;
+        let img = match img {
+            image::DynamicImage::ImageRgba8(img) => img,
+            img => img.to_rgba8(),
+        };
 
-    /// Creates a [`BindGroupLayout`](`wgpu::BindGroupLayout`) for "textured" pipeline's fragment shader's binding.
-    // FIXME: Maybe should be moved out of `impl Texture`?
-    fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Texture Bind Group Layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-        })
+        Texture::from_image(context, &img, settings)
     }
-}
-
-impl<'a> TextureOp<TextureContext<'a>> for Texture {
-    type Error = TextureError;
-}
-
-/// Texture creation or update error.
-#[derive(Debug)]
-pub enum TextureError {
-    ImageError(image::error::ImageError),
-}
 
-impl Display for TextureError {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            TextureError::ImageError(e) => write!(f, "Error loading image: {}", e),
+    /// Updates the `size` rectangle at `offset` with the RGBA pixels of `memory`, whose rows are `bytes_per_row` apart.
+    ///
+    /// This uploads a region of a larger image directly, without packing its rows first.
+    /// Returns [`TextureError::MipmappedUpdate`] if the texture has mipmaps, e.g. generated with
+    /// [`TextureSettings::generate_mipmap`], since only its base level would be updated.
+    pub fn update_with_stride<'a>(
+        &mut self,
+        context: &mut TextureContext<'a>,
+        memory: &[u8],
+        [x, y]: [u32; 2],
+        [width, height]: [u32; 2],
+        bytes_per_row: usize,
+    ) -> Result<(), TextureError> {
+        let Texture { ref texture, .. } = self;
+        if texture.mip_level_count() > 1 {
+            return Err(TextureError::MipmappedUpdate);
+        }
+        context.check_upload(
+            [self.width, self.height],
+            [x, y],
+            [width, height],
+            bytes_per_row,
+            memory.len(),
+        )?;
+        let origin = wgpu::Origin3d { x, y, z: 0 };
+        let TextureContext {
+            device,
+            queue,
+            staged_uploads,
+            ..
+        } = context;
+        if let Some(staged_uploads) = staged_uploads {
+            staged_uploads.write(
+                device,
+                texture,
+                origin,
+                [width, height],
+                memory,
+                bytes_per_row,
+            );
+            return Ok(());
         }
-    }
-}
 
-#[allow(clippy::float_cmp)]
-impl<'a> CreateTexture<TextureContext<'a>> for Texture {
-    fn create<S: Into<[u32; 2]>>(
-        TextureContext { device, queue }: &mut TextureContext<'a>,
-        _format: Format,
-        memory: &[u8],
-        size: S,
-        settings: &TextureSettings,
-    ) -> Result<Self, TextureError> {
-        let [width, height] = size.into();
-        let texture_size = wgpu::Extent3d {
+        let size = wgpu::Extent3d {
             width,
             height,
             depth_or_array_layers: 1,
         };
 
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Diffuse Texture"),
-            size: texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[wgpu::TextureFormat::Rgba8UnormSrgb],
-        });
-
         queue.write_texture(
             wgpu::ImageCopyTexture {
-                texture: &texture,
+                texture,
                 mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
+                origin,
                 aspect: wgpu::TextureAspect::All,
             },
-            memory,
+            &loader::pad_rows(memory, bytes_per_row, [width, height]),
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(4 * width),
+                bytes_per_row: Some(loader::padded_row_size(width) as u32),
                 rows_per_image: Some(height),
             },
-            texture_size,
+            size,
         );
+        Ok(())
+    }
+
+    /// Creates a `Texture` with `img`.
+    pub fn from_image<'a>(
+        context: &mut TextureContext<'a>,
+        img: &image::RgbaImage,
+        settings: &TextureSettings,
+    ) -> Result<Self, TextureError> {
+        let (width, height) = img.dimensions();
+        CreateTexture::create(context, Format::Rgba8, img, [width, height], settings)
+    }
+
+    /// Updates the region at `offset` with `img`, converting it to 8-bit RGBA if it isn't already.
+    ///
+    /// Returns [`TextureError::OutOfBounds`] if `img` doesn't fit in the texture at `offset`.
+    pub fn update_from_image<'a>(
+        &mut self,
+        context: &mut TextureContext<'a>,
+        img: &image::DynamicImage,
+        offset: [u32; 2],
+    ) -> Result<(), TextureError> {
+        let converted;
+        let rgba = match img {
+            image::DynamicImage::ImageRgba8(rgba) => rgba,
+            _ => {
+                converted = img.to_rgba8();
+                &converted
+            }
+        };
+        let (width, height) = rgba.dimensions();
+        self.update_with_stride(context, rgba, offset, [width, height], 4 * width as usize)
+    }
+
+    /// Returns the underlying `wgpu::Texture`, e.g. to use it in custom passes.
+    pub fn as_wgpu(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// Destroys the texture, releasing its GPU memory now rather than once the GPU is done with every bind group of it.
+    ///
+    /// Command buffers drawing the texture must be submitted before, since submitting them afterwards is a validation error.
+    pub fn destroy(self) {
+        self.texture.destroy();
+    }
+
+    /// Returns the view of all the texture, which the texture is sampled through.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Returns `true` if the texture can be drawn into, as created by [`Wgpu2d::create_render_target`].
+    pub fn is_render_target(&self) -> bool {
+        self.usage.contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    }
+
+    /// Reads the pixel at `x`, `y`, waiting for the GPU to finish the pending work.
+    ///
+    /// Only a single row is copied, padded to the row alignment wgpu requires,
+    /// so this is cheap enough for picking on a rendered ID texture.
+    /// Bytes are in the channel order of the texture format, which is RGBA for textures created from images.
+    pub fn read_pixel<'a>(
+        &self,
+        context: &mut TextureContext<'a>,
+        x: u32,
+        y: u32,
+    ) -> Result<[u8; 4], TextureError> {
+        if x >= self.width || y >= self.height {
+            return Err(TextureError::OutOfBounds {
+                offset: [x, y],
+                size: [1, 1],
+            });
+        }
+        let TextureContext { device, queue, .. } = context;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pixel Read Buffer"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        queue.submit(Some(encode(device, |encoder| {
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x, y, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                        rows_per_image: None,
+                    },
+                },
+                wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            )
+        })));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .unwrap_or(Err(wgpu::BufferAsyncError))
+            .map_err(TextureError::ReadFailed)?;
+
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&slice.get_mapped_range()[..4]);
+        buffer.unmap();
+        Ok(pixel)
+    }
+
+    /// Creates an empty RGBA texture of `format`, `size` and `mip_level_count` levels, which data can be copied into.
+    fn create_rgba(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        [width, height]: [u32; 2],
+        mip_level_count: u32,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Diffuse Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    /// Returns the sampler border color of `settings`.
+    ///
+    /// wgpu only supports transparent black, opaque black and opaque white border colors.
+    /// Other colors are an error if a wrap mode is `Wrap::ClampToBorder`, and ignored otherwise.
+    #[allow(clippy::float_cmp)]
+    fn border_color(
+        settings: &TextureSettings,
+    ) -> Result<Option<wgpu::SamplerBorderColor>, TextureError> {
+        let color = settings.get_border_color();
+        Ok(if color == [0.0; 4] {
+            Some(wgpu::SamplerBorderColor::TransparentBlack)
+        } else if color == [0.0, 0.0, 0.0, 1.0] {
+            Some(wgpu::SamplerBorderColor::OpaqueBlack)
+        } else if color == [1.0; 4] {
+            Some(wgpu::SamplerBorderColor::OpaqueWhite)
+        } else if matches!(settings.get_wrap_u(), Wrap::ClampToBorder)
+            || matches!(settings.get_wrap_v(), Wrap::ClampToBorder)
+        {
+            return Err(TextureError::UnsupportedBorderColor(color));
+        } else {
+            None
+        })
+    }
+
+    /// Returns the supported border color nearest to the one of `settings`, where [`Texture::border_color`] can't fail.
+    ///
+    /// Textures created without a [`TextureContext`], which can't return an error,
+    /// clamp to this color instead of dropping an unsupported one.
+    fn nearest_border_color(settings: &TextureSettings) -> Option<wgpu::SamplerBorderColor> {
+        Texture::border_color(settings).unwrap_or_else(|_| {
+            let color = settings.get_border_color();
+            let distance = |other: [f32; 4]| -> f32 {
+                color
+                    .iter()
+                    .zip(&other)
+                    .map(|(a, b)| (a - b) * (a - b))
+                    .sum()
+            };
+            let candidates = [
+                (wgpu::SamplerBorderColor::TransparentBlack, [0.0; 4]),
+                (wgpu::SamplerBorderColor::OpaqueBlack, [0.0, 0.0, 0.0, 1.0]),
+                (wgpu::SamplerBorderColor::OpaqueWhite, [1.0; 4]),
+            ];
+            candidates
+                .iter()
+                .min_by(|(_, a), (_, b)| distance(*a).total_cmp(&distance(*b)))
+                .map(|&(border_color, _)| border_color)
+        })
+    }
 
+    /// Creates a `Texture` sampling `texture` of `size` with `sampler`.
+    fn from_wgpu(
+        device: &wgpu::Device,
+        texture: wgpu::Texture,
+        [width, height]: [u32; 2],
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
         let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
             label: Some("Texture View"),
             ..Default::default()
         });
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        let bind_group_layout = Texture::create_bind_group_layout(device);
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Texture Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Texture {
+            usage: texture.usage(),
+            texture,
+            view: texture_view,
+            sampler,
+            bind_group: Arc::new(bind_group),
+            width,
+            height,
+        }
+    }
+
+    /// Creates the sampler of textures with `settings`, with anisotropic filtering up to `anisotropy`.
+    ///
+    /// Textures created through a [`TextureContext`] share theirs with [`TextureContext::sampler`] instead.
+    fn create_sampler(
+        device: &wgpu::Device,
+        settings: &TextureSettings,
+        anisotropy: u16,
+    ) -> wgpu::Sampler {
+        device.create_sampler(&Texture::sampler_descriptor(settings, anisotropy))
+    }
+
+    /// Returns the descriptor of the sampler of textures with `settings`, with anisotropic filtering up to `anisotropy`.
+    ///
+    /// Border colors [`Texture::border_color`] doesn't support are approximated with [`Texture::nearest_border_color`].
+    fn sampler_descriptor(
+        settings: &TextureSettings,
+        anisotropy: u16,
+    ) -> wgpu::SamplerDescriptor<'static> {
+        wgpu::SamplerDescriptor {
             address_mode_u: match settings.get_wrap_u() {
                 Wrap::ClampToEdge => wgpu::AddressMode::ClampToEdge,
                 Wrap::Repeat => wgpu::AddressMode::Repeat,
@@ -459,48 +918,223 @@ impl<'a> CreateTexture<TextureContext<'a>> for Texture {
                 Filter::Linear => wgpu::FilterMode::Linear,
                 Filter::Nearest => wgpu::FilterMode::Nearest,
             },
-            border_color: if settings.get_border_color() == [0.0; 4] {
-                Some(wgpu::SamplerBorderColor::TransparentBlack)
-            } else if settings.get_border_color() == [0.0, 0.0, 0.0, 1.0] {
-                Some(wgpu::SamplerBorderColor::OpaqueBlack)
-            } else if settings.get_border_color() == [1.0; 4] {
-                Some(wgpu::SamplerBorderColor::OpaqueWhite)
+            // wgpu only allows anisotropy with linear filtering throughout.
+            anisotropy_clamp: if [
+                settings.get_mag(),
+                settings.get_min(),
+                settings.get_mipmap(),
+            ]
+            .iter()
+            .all(|filter| matches!(filter, Filter::Linear))
+            {
+                anisotropy.clamp(1, MAX_ANISOTROPY)
             } else {
-                None
+                1
             },
+            border_color: Texture::nearest_border_color(settings),
             ..Default::default()
-        });
-
-        let bind_group_layout = Texture::create_bind_group_layout(device);
+        }
+    }
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Texture Bind Group"),
-            layout: &bind_group_layout,
+    /// Creates a [`BindGroupLayout`](`wgpu::BindGroupLayout`) for "textured" pipeline's fragment shader's binding.
+    // FIXME: Maybe should be moved out of `impl Texture`?
+    fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Texture Bind Group Layout"),
             entries: &[
-                wgpu::BindGroupEntry {
+                wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
                 },
-                wgpu::BindGroupEntry {
+                wgpu::BindGroupLayoutEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
                 },
             ],
-        });
+        })
+    }
+}
+
+impl<'a> TextureOp<TextureContext<'a>> for Texture {
+    type Error = TextureError;
+}
+
+/// Texture creation or update error.
+#[derive(Debug)]
+pub enum TextureError {
+    ImageError(image::error::ImageError),
+    /// The texture size is zero or exceeds the device limits.
+    InvalidSize([u32; 2]),
+    /// The region to update doesn't fit in the texture.
+    OutOfBounds {
+        offset: [u32; 2],
+        size: [u32; 2],
+    },
+    /// Fewer bytes were given than the texture region needs.
+    DataTooShort {
+        expected: usize,
+        actual: usize,
+    },
+    /// The texture couldn't be read back from the GPU.
+    ReadFailed(wgpu::BufferAsyncError),
+    /// The border color isn't one wgpu supports, and a wrap mode clamps to it.
+    UnsupportedBorderColor([f32; 4]),
+    /// The texture was drawn into, but wasn't created as a render target.
+    NotRenderTarget,
+    /// The texture was drawn into, but its format isn't the one of the pipelines.
+    IncompatibleFormat(wgpu::TextureFormat),
+    /// The texture was read back, but its format isn't 8-bit RGBA or BGRA.
+    UnreadableFormat(wgpu::TextureFormat),
+    /// The multisampled target was drawn into, but its sample count isn't the one of the pipelines.
+    IncompatibleSampleCount(u32),
+    /// Rows of pixel data are closer than this number of bytes, the size of a row.
+    InvalidStride(usize),
+    /// The texture layer drawn into doesn't exist.
+    InvalidLayer(u32),
+    /// The texture was drawn into, but only 2D textures and texture arrays can be.
+    UnsupportedDimension(wgpu::TextureDimension),
+    /// The texture was updated, but it has mipmaps, which would keep the previous image.
+    MipmappedUpdate,
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TextureError::ImageError(e) => write!(f, "Error loading image: {}", e),
+            TextureError::InvalidSize([width, height]) => {
+                write!(f, "Invalid texture size: {}x{}", width, height)
+            }
+            TextureError::OutOfBounds {
+                offset: [x, y],
+                size: [width, height],
+            } => write!(
+                f,
+                "Region of {}x{} at ({}, {}) is out of the texture bounds",
+                width, height, x, y
+            ),
+            TextureError::DataTooShort { expected, actual } => write!(
+                f,
+                "Texture data is too short: expected {} bytes, got {}",
+                expected, actual
+            ),
+            TextureError::ReadFailed(e) => write!(f, "Error reading texture: {}", e),
+            TextureError::UnsupportedBorderColor(color) => write!(
+                f,
+                "Unsupported border color {:?}: only transparent black, opaque black and opaque white are supported",
+                color
+            ),
+            TextureError::NotRenderTarget => write!(f, "Texture is not a render target"),
+            TextureError::IncompatibleFormat(format) => {
+                write!(f, "Texture format {:?} doesn't match the pipelines", format)
+            }
+            TextureError::UnreadableFormat(format) => {
+                write!(f, "Texture format {:?} can't be read back as RGBA", format)
+            }
+            TextureError::IncompatibleSampleCount(count) => {
+                write!(f, "Sample count {} doesn't match the pipelines", count)
+            }
+            TextureError::InvalidStride(bytes_per_row) => write!(
+                f,
+                "Rows of {} bytes are shorter than the texture region",
+                bytes_per_row
+            ),
+            TextureError::InvalidLayer(layer) => write!(f, "Texture has no layer {}", layer),
+            TextureError::UnsupportedDimension(dimension) => {
+                write!(f, "Textures of dimension {:?} can't be drawn into", dimension)
+            }
+            TextureError::MipmappedUpdate => write!(f, "Textures with mipmaps can't be updated"),
+        }
+    }
+}
+
+impl<'a> CreateTexture<TextureContext<'a>> for Texture {
+    fn create<S: Into<[u32; 2]>>(
+        context: &mut TextureContext<'a>,
+        _format: Format,
+        memory: &[u8],
+        size: S,
+        settings: &TextureSettings,
+    ) -> Result<Self, TextureError> {
+        let [width, height] = size.into();
+        context.check_upload(
+            [width, height],
+            [0, 0],
+            [width, height],
+            4 * width as usize,
+            memory.len(),
+        )?;
+        Texture::border_color(settings)?;
+        let format = context.texture_format();
+        let sampler = context.sampler(settings);
+        let TextureContext { device, queue, .. } = context;
+        // The full chain, down to 1x1, if mipmaps are generated.
+        let mip_level_count = if settings.get_generate_mipmap() {
+            32 - width.max(height).leading_zeros()
+        } else {
+            1
+        };
+        let texture = Texture::create_rgba(device, format, [width, height], mip_level_count);
+
+        let write_level = |mip_level, memory: &[u8], [width, height]: [u32; 2]| {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &loader::pad_rows(memory, 4 * width as usize, [width, height]),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(loader::padded_row_size(width) as u32),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            )
+        };
+        write_level(0, memory, [width, height]);
+        if mip_level_count > 1 {
+            // Each level is downsampled from the previous one on the CPU.
+            let len = 4 * width as usize * height as usize;
+            let mut level =
+                image::RgbaImage::from_raw(width, height, memory[..len].to_vec()).unwrap();
+            for mip_level in 1..mip_level_count {
+                let size = [(width >> mip_level).max(1), (height >> mip_level).max(1)];
+                level = image::imageops::resize(
+                    &level,
+                    size[0],
+                    size[1],
+                    image::imageops::FilterType::Triangle,
+                );
+                write_level(mip_level, &level, size);
+            }
+        }
 
-        Ok(Self {
+        Ok(Texture::from_wgpu(
+            device,
             texture,
-            bind_group,
-            width,
-            height,
-        })
+            [width, height],
+            sampler,
+        ))
     }
 }
 
 impl<'a> UpdateTexture<TextureContext<'a>> for Texture {
     fn update<O, S>(
         &mut self,
-        TextureContext { queue, .. }: &mut TextureContext<'a>,
+        context: &mut TextureContext<'a>,
         _format: Format,
         memory: &[u8],
         offset: O,
@@ -510,33 +1144,8 @@ impl<'a> UpdateTexture<TextureContext<'a>> for Texture {
         O: Into<[u32; 2]>,
         S: Into<[u32; 2]>,
     {
-        let Texture { ref texture, .. } = self;
-        let [x, y] = offset.into();
-        let [width, height] = size.into();
-
-        let origin = wgpu::Origin3d { x, y, z: 0 };
-        let size = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
-
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture,
-                mip_level: 0,
-                origin,
-                aspect: wgpu::TextureAspect::All,
-            },
-            memory,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * width),
-                rows_per_image: Some(height),
-            },
-            size,
-        );
-        Ok(())
+        let size = size.into();
+        self.update_with_stride(context, memory, offset.into(), size, 4 * size[0] as usize)
     }
 }
 
@@ -546,341 +1155,3591 @@ impl ImageSize for Texture {
     }
 }
 
-/// The resource needed for rendering 2D.
-pub struct Wgpu2d<'a> {
-    device: &'a wgpu::Device,
-    colored_render_pipelines: PsoStencil<wgpu::RenderPipeline>,
-    textured_render_pipelines: PsoStencil<wgpu::RenderPipeline>,
+/// Compact variant of [`ColoredPipelineInput`], used with [`Wgpu2dSettings::compact_vertices`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct CompactColoredPipelineInput {
+    position: [f32; 2],
+    color: [u8; 4],
 }
 
-impl<'a> Wgpu2d<'a> {
-    /// Creates a new `Wgpu2d`.
-    pub fn new<'b>(device: &'a wgpu::Device, config: &'b wgpu::SurfaceConfiguration) -> Self {
-        let colored_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Colored Pipeline Layout"),
-                bind_group_layouts: &[],
-                push_constant_ranges: &[],
-            });
-
-        let colored_shader_module =
-            device.create_shader_module(wgpu::include_wgsl!("colored.wgsl"));
+impl CompactColoredPipelineInput {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<CompactColoredPipelineInput>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Unorm8x4,
+                },
+            ],
+        }
+    }
+}
 
-        let colored_render_pipelines = PsoStencil::new(|blend, stencil| {
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Colored Render Pipeline"),
-                layout: Some(&colored_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &colored_shader_module,
-                    entry_point: "vs_main",
-                    buffers: &[ColoredPipelineInput::desc()],
-                },
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: None,
-                    unclipped_depth: true,
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    conservative: false,
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth24PlusStencil8,
-                    depth_write_enabled: false,
-                    depth_compare: wgpu::CompareFunction::Always,
-                    stencil,
-                    bias: wgpu::DepthBiasState::default(),
-                }),
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &colored_shader_module,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend,
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                multiview: None,
-            })
-        });
+impl From<&ColoredPipelineInput> for CompactColoredPipelineInput {
+    fn from(input: &ColoredPipelineInput) -> Self {
+        CompactColoredPipelineInput {
+            position: input.position,
+            color: to_unorm8x4(input.color),
+        }
+    }
+}
 
-        let textured_bind_group_layout = Texture::create_bind_group_layout(device);
+/// Compact variant of [`TexturedPipelineInput`], used with [`Wgpu2dSettings::compact_vertices`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct CompactTexturedPipelineInput {
+    xy: [f32; 2],
+    /// Bits of the `f16` texture coordinates.
+    uv: [u16; 2],
+    color: [u8; 4],
+    alpha_test: f32,
+}
 
-        let textured_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Textured Pipeline Layout"),
-                bind_group_layouts: &[&textured_bind_group_layout],
+impl CompactTexturedPipelineInput {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<CompactTexturedPipelineInput>()
+                as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float16x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Unorm8x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32,
+                },
+            ],
+        }
+    }
+}
+
+impl From<&TexturedPipelineInput> for CompactTexturedPipelineInput {
+    fn from(input: &TexturedPipelineInput) -> Self {
+        let [u, v] = input.uv;
+        CompactTexturedPipelineInput {
+            xy: input.xy,
+            uv: [
+                half::f16::from_f32(u).to_bits(),
+                half::f16::from_f32(v).to_bits(),
+            ],
+            color: to_unorm8x4(input.color),
+            alpha_test: input.alpha_test,
+        }
+    }
+}
+
+/// Converts a color to the bytes of a `Unorm8x4` vertex attribute.
+fn to_unorm8x4(color: [f32; 4]) -> [u8; 4] {
+    color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Default initial capacity, in vertices, of the vertex buffers.
+const DEFAULT_VERTEX_BUFFER_CAPACITY: u32 = 1024;
+
+/// Number of stencil and multisampled texture sizes kept by a [`Wgpu2d`], beyond which they are all freed,
+/// so surfaces resized without [`Wgpu2d::resize`] don't pile them up.
+const MAX_CACHED_TEXTURE_SIZES: usize = 4;
+
+/// Anti-aliasing of the edges of shapes, chosen by [`Wgpu2dSettings::anti_alias`].
+///
+/// With anti-aliasing, frames are drawn offscreen, onto a transparent texture rather than
+/// the previous contents of the output, so they should start by clearing the color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiAlias {
+    /// No anti-aliasing.
+    None,
+    /// Multisample anti-aliasing, with this number of samples per pixel, usually 4.
+    ///
+    /// No anti-aliasing is done if the device doesn't support the number of samples for the target format.
+    Msaa(u32),
+    /// Fast approximate anti-aliasing, smoothing edges of the whole frame in a post-process pass.
+    ///
+    /// It is cheaper than multisampling on some GPUs, but also slightly blurs textures, including pixel art.
+    Fxaa,
+}
+
+/// Settings used to create a [`Wgpu2d`].
+#[derive(Debug, Clone)]
+pub struct Wgpu2dSettings {
+    /// Initial capacity, in vertices, of the "colored" and "textured" vertex buffers.
+    ///
+    /// The buffers grow when a frame needs more vertices than they can hold.
+    /// Apps that know their peak vertex count can set it here to avoid reallocating them during the first frames.
+    pub vertex_buffer_capacity: u32,
+    /// Depth bias applied by the "colored" and "textured" pipelines.
+    ///
+    /// It only has an effect with depth testing, where it offsets layers drawn at the same depth.
+    pub depth_bias: wgpu::DepthBiasState,
+    /// Whether tint colors of textured draws are converted from sRGB to linear before multiplying texels.
+    ///
+    /// Texels of sRGB textures are decoded to linear when sampled, so multiplying them by an sRGB tint makes it too dark.
+    /// This is off by default to keep the output of existing apps.
+    pub linear_tint: bool,
+    /// Format of the render targets to build the pipelines for, instead of the surface format.
+    ///
+    /// Compact formats such as `Rgb10a2Unorm` save bandwidth on mobile and tiled GPUs.
+    /// If the device can't render to and blend into the format, `Rgba8UnormSrgb` is used instead;
+    /// [`Wgpu2d::format`] returns the chosen one, which output views must then use.
+    pub target_format: Option<wgpu::TextureFormat>,
+    /// Format of the stencil textures to prefer, instead of the first of `Depth24PlusStencil8`
+    /// and `Depth32FloatStencil8` the device can render to.
+    ///
+    /// It is ignored if it has no stencil aspect or the device can't render to it; [`Wgpu2d::stencil_format`] returns the chosen one.
+    pub depth_stencil_format: Option<wgpu::TextureFormat>,
+    /// Whether vertices are uploaded in a compact format, halving the size of vertex buffers.
+    ///
+    /// Colors are stored as 8-bit unsigned normalized channels, and texture coordinates as 16-bit floats.
+    /// The output differs from the full format by at most one step of 8-bit color,
+    /// but texture coordinates far from 0, such as the local coordinates of large gradients, lose precision.
+    pub compact_vertices: bool,
+    /// Whether vertex positions given to the `tri_list*` methods are checked for NaN and infinite coordinates.
+    ///
+    /// Such positions, e.g. from a transform scaled by 0 and inverted, corrupt draws or hang some drivers.
+    /// Draws with one are skipped and reported by [`WgpuGraphics::invalid_vertices`].
+    /// This is off by default, so release builds don't pay for the check.
+    pub debug_validate: bool,
+    /// Whether the "colored" and "textured" pipelines premultiply their output colors by their alpha,
+    /// and blend them as premultiplied colors.
+    ///
+    /// Alpha blending then composes with additive blending: a color added with `Blend::Add`
+    /// contributes as much as the same color drawn opaque with `Blend::Alpha`, scaled by its alpha.
+    /// This is off by default to keep the output of existing apps.
+    pub premultiply_vertex_colors: bool,
+    /// Whether textured draws with `Blend::Alpha` blend their texels as premultiplied colors,
+    /// with `wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING`.
+    ///
+    /// This suits textures whose colors are already premultiplied by their alpha, e.g. filtered sprites,
+    /// whose edges are otherwise darkened twice. Other blend modes and colored draws are unchanged.
+    pub premultiplied_alpha: bool,
+    /// Anti-aliasing of the edges of shapes.
+    pub anti_alias: AntiAlias,
+    /// Whether [`Wgpu2d::draw`] accumulates frames: each frame is drawn over the previous one,
+    /// unless it clears the color.
+    ///
+    /// Surface textures don't reliably keep their contents between frames, so frames are drawn into a texture
+    /// kept by the `Wgpu2d`, which is then copied onto the output. This suits apps updating small regions,
+    /// or drawing incrementally. The texture starts transparent, and again whenever the surface size changes.
+    /// No anti-aliasing is done in this mode, whatever [`Wgpu2dSettings::anti_alias`] is.
+    pub accumulate: bool,
+    /// Whether the "colored" and "textured" pipelines test and write depth, so draws can be layered out of order
+    /// with [`WgpuGraphics::set_depth`].
+    ///
+    /// Draws pass the test where their depth is less than or equal to the depth drawn so far,
+    /// which is cleared to 1 at the start of each frame. Translucent draws still blend in draw order,
+    /// so they should be drawn after the opaque ones behind them.
+    /// It has no effect if the stencil format has no depth aspect. This is off by default, keeping the painter's order.
+    pub depth_test: bool,
+    /// Whether the GPU time of each render pass is measured with timestamp queries, read with [`Wgpu2d::gpu_timings`].
+    ///
+    /// It requires `wgpu::Features::TIMESTAMP_QUERY`, and does nothing on devices without it.
+    /// This is off by default, so frames don't pay for the queries.
+    pub gpu_timing: bool,
+    /// Whether the pipelines writing clip masks, with `Stencil::Clip` and `Stencil::Increment`,
+    /// rasterize every pixel their triangles touch, so masks cover antialiased shape edges without seams.
+    ///
+    /// It requires `wgpu::Features::CONSERVATIVE_RASTERIZATION`, and falls back to standard rasterization on devices without it;
+    /// [`Wgpu2d::conservative_rasterization`] tells whether it is used. This is off by default.
+    pub conservative_rasterization: bool,
+    /// Bits of the stencil buffer the pipelines compare, 255 by default.
+    ///
+    /// With [`Wgpu2dSettings::stencil_write_mask`], this confines clips and path fills to some bits,
+    /// leaving the others to masks drawn by other renderers. Clearing the stencil buffer still clears every bit.
+    pub stencil_read_mask: u8,
+    /// Bits of the stencil buffer the pipelines write, 255 by default.
+    pub stencil_write_mask: u8,
+}
+
+impl Default for Wgpu2dSettings {
+    fn default() -> Self {
+        Wgpu2dSettings {
+            vertex_buffer_capacity: DEFAULT_VERTEX_BUFFER_CAPACITY,
+            depth_bias: wgpu::DepthBiasState::default(),
+            linear_tint: false,
+            target_format: None,
+            depth_stencil_format: None,
+            compact_vertices: false,
+            debug_validate: false,
+            premultiply_vertex_colors: false,
+            premultiplied_alpha: false,
+            anti_alias: AntiAlias::None,
+            accumulate: false,
+            depth_test: false,
+            gpu_timing: false,
+            conservative_rasterization: false,
+            stencil_read_mask: 255,
+            stencil_write_mask: 255,
+        }
+    }
+}
+
+impl Wgpu2dSettings {
+    /// Creates a new `Wgpu2dSettings` with default values.
+    pub fn new() -> Self {
+        Wgpu2dSettings::default()
+    }
+
+    /// Sets the initial capacity, in vertices, of the vertex buffers.
+    pub fn vertex_buffer_capacity(mut self, vertex_buffer_capacity: u32) -> Self {
+        self.vertex_buffer_capacity = vertex_buffer_capacity;
+        self
+    }
+
+    /// Sets the depth bias of the pipelines.
+    pub fn depth_bias(mut self, depth_bias: wgpu::DepthBiasState) -> Self {
+        self.depth_bias = depth_bias;
+        self
+    }
+
+    /// Sets whether tint colors of textured draws are linearized.
+    pub fn linear_tint(mut self, linear_tint: bool) -> Self {
+        self.linear_tint = linear_tint;
+        self
+    }
+
+    /// Sets the format of the render targets, instead of the surface format.
+    pub fn target_format(mut self, target_format: wgpu::TextureFormat) -> Self {
+        self.target_format = Some(target_format);
+        self
+    }
+
+    /// Sets the format of the stencil textures to prefer.
+    pub fn depth_stencil_format(mut self, depth_stencil_format: wgpu::TextureFormat) -> Self {
+        self.depth_stencil_format = Some(depth_stencil_format);
+        self
+    }
+
+    /// Sets whether vertices are uploaded in a compact format.
+    pub fn compact_vertices(mut self, compact_vertices: bool) -> Self {
+        self.compact_vertices = compact_vertices;
+        self
+    }
+
+    /// Sets whether vertex positions are checked for NaN and infinite coordinates.
+    pub fn debug_validate(mut self, debug_validate: bool) -> Self {
+        self.debug_validate = debug_validate;
+        self
+    }
+
+    /// Sets whether the colors of the "colored" and "textured" pipelines are premultiplied.
+    pub fn premultiply_vertex_colors(mut self, premultiply_vertex_colors: bool) -> Self {
+        self.premultiply_vertex_colors = premultiply_vertex_colors;
+        self
+    }
+
+    /// Sets whether textured draws with `Blend::Alpha` blend their texels as premultiplied colors.
+    pub fn premultiplied_alpha(mut self, premultiplied_alpha: bool) -> Self {
+        self.premultiplied_alpha = premultiplied_alpha;
+        self
+    }
+
+    /// Sets the anti-aliasing of the edges of shapes.
+    pub fn anti_alias(mut self, anti_alias: AntiAlias) -> Self {
+        self.anti_alias = anti_alias;
+        self
+    }
+
+    /// Sets whether [`Wgpu2d::draw`] accumulates frames.
+    pub fn accumulate(mut self, accumulate: bool) -> Self {
+        self.accumulate = accumulate;
+        self
+    }
+
+    /// Sets whether the pipelines test and write depth.
+    pub fn depth_test(mut self, depth_test: bool) -> Self {
+        self.depth_test = depth_test;
+        self
+    }
+
+    /// Sets whether the GPU time of each render pass is measured.
+    pub fn gpu_timing(mut self, gpu_timing: bool) -> Self {
+        self.gpu_timing = gpu_timing;
+        self
+    }
+
+    /// Sets whether the pipelines writing clip masks use conservative rasterization.
+    pub fn conservative_rasterization(mut self, conservative_rasterization: bool) -> Self {
+        self.conservative_rasterization = conservative_rasterization;
+        self
+    }
+
+    /// Sets the bits of the stencil buffer the pipelines compare.
+    pub fn stencil_read_mask(mut self, stencil_read_mask: u8) -> Self {
+        self.stencil_read_mask = stencil_read_mask;
+        self
+    }
+
+    /// Sets the bits of the stencil buffer the pipelines write.
+    pub fn stencil_write_mask(mut self, stencil_write_mask: u8) -> Self {
+        self.stencil_write_mask = stencil_write_mask;
+        self
+    }
+
+    /// Sets the number of samples per pixel of multisample anti-aliasing, or disables anti-aliasing with 1.
+    pub fn sample_count(self, sample_count: u32) -> Self {
+        self.anti_alias(if sample_count > 1 {
+            AntiAlias::Msaa(sample_count)
+        } else {
+            AntiAlias::None
+        })
+    }
+
+    /// Creates a new `Wgpu2d` with these settings, as [`Wgpu2d::with_settings`] does.
+    pub fn build<'a>(
+        &self,
+        device: &'a wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> Wgpu2d<'a> {
+        Wgpu2d::with_settings(device, config, self)
+    }
+}
+
+/// A vertex or index buffer kept across frames, which grows on demand.
+struct VertexBuffer {
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+    buffer: wgpu::Buffer,
+}
+
+impl VertexBuffer {
+    /// Creates a new `VertexBuffer` which can hold `size` bytes, used as `usage`.
+    fn new(
+        device: &wgpu::Device,
+        label: &'static str,
+        usage: wgpu::BufferUsages,
+        size: wgpu::BufferAddress,
+    ) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size.max(wgpu::COPY_BUFFER_ALIGNMENT),
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        VertexBuffer {
+            label,
+            usage,
+            buffer,
+        }
+    }
+
+    /// Records a copy of `data` into the buffer, reallocating it first if `data` doesn't fit.
+    ///
+    /// `data` is copied from a staging buffer of its own, which wgpu frees once the copy is done,
+    /// so the encoder can be submitted along with any other, in any order.
+    fn upload(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, data: &[u8]) {
+        let size = match wgpu::BufferSize::new(data.len() as wgpu::BufferAddress) {
+            Some(size) => size,
+            None => return,
+        };
+        if size.get() > self.buffer.size() {
+            *self = VertexBuffer::new(
+                device,
+                self.label,
+                self.usage,
+                size.get().next_power_of_two(),
+            );
+        }
+        let staging = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Staging Buffer"),
+            contents: data,
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+        encoder.copy_buffer_to_buffer(&staging, 0, &self.buffer, 0, size.get());
+    }
+}
+
+/// Vertex buffers of both pipelines and of the "multitexture" one, and the index buffer of indexed draws.
+struct VertexBuffers {
+    colored: VertexBuffer,
+    textured: VertexBuffer,
+    multitexture: VertexBuffer,
+    indices: VertexBuffer,
+    /// Vertices converted to the compact format, kept to reuse their allocations.
+    compact: Option<(
+        Vec<CompactColoredPipelineInput>,
+        Vec<CompactTexturedPipelineInput>,
+    )>,
+}
+
+impl VertexBuffers {
+    /// Creates new `VertexBuffers` which can hold `capacity` vertices each.
+    ///
+    /// If `compact` is true, vertices are converted to the compact format when uploaded.
+    fn new(device: &wgpu::Device, capacity: u32, compact: bool) -> Self {
+        let colored_size = capacity as wgpu::BufferAddress
+            * std::mem::size_of::<ColoredPipelineInput>() as wgpu::BufferAddress;
+        let textured_size = capacity as wgpu::BufferAddress
+            * std::mem::size_of::<TexturedPipelineInput>() as wgpu::BufferAddress;
+        VertexBuffers {
+            colored: VertexBuffer::new(
+                device,
+                "Colored Vertex Buffer",
+                wgpu::BufferUsages::VERTEX,
+                colored_size,
+            ),
+            textured: VertexBuffer::new(
+                device,
+                "Textured Vertex Buffer",
+                wgpu::BufferUsages::VERTEX,
+                textured_size,
+            ),
+            multitexture: VertexBuffer::new(
+                device,
+                "Multitexture Vertex Buffer",
+                wgpu::BufferUsages::VERTEX,
+                0,
+            ),
+            indices: VertexBuffer::new(device, "Index Buffer", wgpu::BufferUsages::INDEX, 0),
+            compact: if compact {
+                Some((vec![], vec![]))
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Returns the number of vertices the "colored" and "textured" vertex buffers can hold.
+    fn capacity(&self) -> [u32; 2] {
+        let (colored_size, textured_size) = match self.compact {
+            Some(_) => (
+                std::mem::size_of::<CompactColoredPipelineInput>(),
+                std::mem::size_of::<CompactTexturedPipelineInput>(),
+            ),
+            None => (
+                std::mem::size_of::<ColoredPipelineInput>(),
+                std::mem::size_of::<TexturedPipelineInput>(),
+            ),
+        };
+        [
+            (self.colored.buffer.size() / colored_size as wgpu::BufferAddress) as u32,
+            (self.textured.buffer.size() / textured_size as wgpu::BufferAddress) as u32,
+        ]
+    }
+
+    /// Records copies of vertices into the vertex buffers, and of indices into the index buffer.
+    fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        colored_data: &[ColoredPipelineInput],
+        textured_data: &[TexturedPipelineInput],
+        multitexture_data: &[MultitexturePipelineInput],
+        index_data: &[u32],
+    ) {
+        let VertexBuffers {
+            colored,
+            textured,
+            multitexture,
+            indices,
+            compact,
+        } = self;
+        let (colored_bytes, textured_bytes) = match compact {
+            Some((compact_colored, compact_textured)) => {
+                compact_colored.clear();
+                compact_colored.extend(colored_data.iter().map(CompactColoredPipelineInput::from));
+                compact_textured.clear();
+                compact_textured
+                    .extend(textured_data.iter().map(CompactTexturedPipelineInput::from));
+                (
+                    bytemuck::cast_slice(compact_colored),
+                    bytemuck::cast_slice(compact_textured),
+                )
+            }
+            None => (
+                bytemuck::cast_slice(colored_data),
+                bytemuck::cast_slice(textured_data),
+            ),
+        };
+        colored.upload(device, encoder, colored_bytes);
+        textured.upload(device, encoder, textured_bytes);
+        multitexture.upload(device, encoder, bytemuck::cast_slice(multitexture_data));
+        indices.upload(device, encoder, bytemuck::cast_slice(index_data));
+    }
+}
+
+/// Surface formats the pipelines are tested with.
+///
+/// Both channel orders are listed: colors are always given to wgpu as RGBA,
+/// which maps them to the channel order of the format, so no CPU-side swizzling is needed.
+pub const SUPPORTED_FORMATS: &[wgpu::TextureFormat] = &[
+    wgpu::TextureFormat::Bgra8Unorm,
+    wgpu::TextureFormat::Bgra8UnormSrgb,
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Rgb10a2Unorm,
+    wgpu::TextureFormat::Rgba16Float,
+];
+
+/// Vertex position with a NaN or infinite coordinate, found with [`Wgpu2dSettings::debug_validate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidVertex {
+    /// Index of the vertex in the slice given to the `tri_list*` callback.
+    pub index: usize,
+    /// Position of the vertex.
+    pub position: [f32; 2],
+}
+
+impl Display for InvalidVertex {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Non-finite position {:?} of vertex {}",
+            self.position, self.index
+        )
+    }
+}
+
+/// Counts of the work recorded by a [`WgpuGraphics`], returned by [`WgpuGraphics::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Number of draw calls, one per batch of vertices drawn with the same pipeline, bindings and draw state.
+    pub draw_calls: usize,
+    /// Number of times the main render pass is ended before the end of the frame,
+    /// by a shadow, a group, [`WgpuGraphics::flush`] or [`WgpuGraphics::clear_depth`].
+    pub flushes: usize,
+    /// Number of vertices of the "colored" pipelines.
+    pub colored_vertices: usize,
+    /// Number of vertices of the "textured" pipelines.
+    pub textured_vertices: usize,
+    /// Number of times a textured draw call binds another texture than the previous textured draw call,
+    /// counting the first one.
+    pub texture_switches: usize,
+}
+
+/// A view drawn by [`Wgpu2d::draw_multi_view`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewSpec {
+    /// Rectangle `[x, y, width, height]`, in pixels of the render target, the view is drawn into.
+    pub rect: [u32; 4],
+    /// Camera transform applied to the view's context, in pixels of the view.
+    pub transform: Matrix2d,
+}
+
+/// A render target drawn into with multisampling, and resolved into a texture which can be sampled.
+///
+/// Unlike the frames drawn with multisampling onto other targets, the multisampled contents are kept between draws.
+pub struct MultisampledTarget {
+    multisampled: Option<wgpu::Texture>,
+    resolved: Texture,
+}
+
+impl MultisampledTarget {
+    /// Returns the multisampled texture, or `None` if the [`Wgpu2d`] creating the target doesn't use multisampling,
+    /// in which case frames are drawn directly into the resolved texture.
+    pub fn multisampled(&self) -> Option<&wgpu::Texture> {
+        self.multisampled.as_ref()
+    }
+
+    /// Returns the texture the multisampled texture is resolved into, which can be drawn or read back.
+    pub fn resolved(&self) -> &Texture {
+        &self.resolved
+    }
+}
+
+/// Features the device must be requested with.
+///
+/// None are needed. Vertices are at depth 0, which the viewport depth range maps to the depth of the draws,
+/// set by [`WgpuGraphics::set_depth`] or [`WgpuGraphics::set_viewport_rect`] within 0 to 1,
+/// so draws are never depth clipped, and depth is only compared with [`Wgpu2dSettings::depth_test`].
+/// The pipelines turn depth clipping off anyway if `DEPTH_CLIP_CONTROL` is enabled.
+pub const REQUIRED_FEATURES: wgpu::Features = wgpu::Features::empty();
+
+/// Error creating a [`Wgpu2d`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Wgpu2dError {
+    /// The format of the render targets isn't in [`SUPPORTED_FORMATS`].
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// The device can't render to this stencil format.
+    UnsupportedStencilFormat(wgpu::TextureFormat),
+    /// A custom shader failed validation, e.g. lacking an entry point or not matching the vertex layout.
+    InvalidShader(String),
+}
+
+impl Display for Wgpu2dError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Wgpu2dError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported surface format: {:?}", format)
+            }
+            Wgpu2dError::UnsupportedStencilFormat(format) => {
+                write!(f, "Unsupported stencil format: {:?}", format)
+            }
+            Wgpu2dError::InvalidShader(message) => write!(f, "Invalid shader: {}", message),
+        }
+    }
+}
+
+/// The resource needed for rendering 2D.
+pub struct Wgpu2d<'a> {
+    device: &'a wgpu::Device,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    stencil_format: wgpu::TextureFormat,
+    /// Whether the pipelines test and write depth, with [`Wgpu2dSettings::depth_test`].
+    depth_test: bool,
+    /// Whether the pipelines writing clip masks use conservative rasterization, with [`Wgpu2dSettings::conservative_rasterization`].
+    conservative_rasterization: bool,
+    colored_render_pipelines: PsoStencil<wgpu::RenderPipeline>,
+    line_render_pipelines: PsoStencil<wgpu::RenderPipeline>,
+    textured_render_pipelines: PsoStencil<wgpu::RenderPipeline>,
+    premultiplied_render_pipelines: PsoStencilOnly<wgpu::RenderPipeline>,
+    sdf_render_pipelines: PsoStencil<wgpu::RenderPipeline>,
+    path_pipelines: PathPipelines,
+    /// Pipelines decrementing the stencil buffer, drawn by [`WgpuGraphics::decrement_stencil`].
+    decrement_render_pipelines: PsoBlend<wgpu::RenderPipeline>,
+    gradient_pipelines: GradientPipelines,
+    arc_pipelines: ArcPipelines,
+    multitexture_pipelines: MultitexturePipelines,
+    texture_array_pipelines: TextureArrayPipelines,
+    shadow_pipelines: ShadowPipelines,
+    fxaa_pipeline: Option<FxaaPipeline>,
+    timer: Option<GpuTimer>,
+    textured_bind_group_layout: wgpu::BindGroupLayout,
+    /// Samplers used instead of those of textures by [`WgpuGraphics::set_filter`], with nearest and linear filtering.
+    filter_samplers: [wgpu::Sampler; 2],
+    vertex_buffers: RefCell<VertexBuffers>,
+    /// Stencil textures by size, reused by frames of the same size,
+    /// so frames drawn offscreen at another size don't reallocate the one of the surface.
+    stencil: RefCell<HashMap<[u32; 2], wgpu::Texture>>,
+    /// Multisampled color textures by size, reused as the stencil textures are.
+    multisampled: RefCell<HashMap<[u32; 2], wgpu::Texture>>,
+    /// Size of the surface last passed to [`Wgpu2d::resize`], whose stencil and multisampled textures are freed when it changes.
+    surface_size: [u32; 2],
+    pixel_perfect_target: Option<Texture>,
+    accumulation_target: Option<Texture>,
+    mask_target: Option<Texture>,
+    settings: Wgpu2dSettings,
+    capture: Cell<Capture>,
+}
+
+impl<'a> Wgpu2d<'a> {
+    /// Creates a new `Wgpu2d`.
+    pub fn new<'b>(device: &'a wgpu::Device, config: &'b wgpu::SurfaceConfiguration) -> Self {
+        Wgpu2d::with_settings(device, config, &Wgpu2dSettings::default())
+    }
+
+    /// Creates a new `Wgpu2d`, or returns an error if `config.format` isn't in [`SUPPORTED_FORMATS`],
+    /// or if `device` can't render to the stencil format.
+    ///
+    /// Other formats, such as `Rg11b10Float` which has no alpha, may render with wrong colors or blending.
+    /// Unlike [`Wgpu2d::new`], this doesn't panic in wgpu on devices the pipelines can't be built for,
+    /// as on some WebGL or mobile adapters.
+    pub fn try_new<'b>(
+        device: &'a wgpu::Device,
+        config: &'b wgpu::SurfaceConfiguration,
+    ) -> Result<Self, Wgpu2dError> {
+        Wgpu2d::try_with_settings(device, config, &Wgpu2dSettings::default())
+    }
+
+    /// Creates a new `Wgpu2d` with `settings`, or returns an error if the format of the render targets isn't in [`SUPPORTED_FORMATS`],
+    /// or if `device` can't render to the stencil format.
+    pub fn try_with_settings<'b>(
+        device: &'a wgpu::Device,
+        config: &'b wgpu::SurfaceConfiguration,
+        settings: &Wgpu2dSettings,
+    ) -> Result<Self, Wgpu2dError> {
+        Wgpu2d::check_support(device, config, settings)?;
+        Ok(Wgpu2d::with_settings(device, config, settings))
+    }
+
+    /// Creates a new `Wgpu2d` with `settings`, drawing with the `colored` and `textured` WGSL shaders instead of the built-in ones,
+    /// e.g. for a custom vertex transform or a fragment effect.
+    ///
+    /// Shaders left to `None` are the built-in ones. Both must have a `vs_main` vertex entry point
+    /// taking the vertex attributes of the built-in shader at the same locations, and the textured one the same bindings.
+    /// The colored shader needs a `fs_main` fragment entry point, or `fs_premultiplied` with [`Wgpu2dSettings::premultiply_vertex_colors`].
+    /// The textured one needs `fs_premultiplied`, and the `fs_main`, `fs_linear_tint`, `fs_premultiplied`
+    /// or `fs_linear_tint_premultiplied` entry point matching [`Wgpu2dSettings::linear_tint`] and [`Wgpu2dSettings::premultiply_vertex_colors`].
+    ///
+    /// Returns [`Wgpu2dError::InvalidShader`] if the pipelines fail validation with the shaders,
+    /// besides the errors of [`Wgpu2d::try_with_settings`]. Devices reporting validation errors asynchronously,
+    /// such as on the web, aren't waited for.
+    pub fn try_with_shaders<'b>(
+        device: &'a wgpu::Device,
+        config: &'b wgpu::SurfaceConfiguration,
+        settings: &Wgpu2dSettings,
+        colored: Option<wgpu::ShaderModuleDescriptor>,
+        textured: Option<wgpu::ShaderModuleDescriptor>,
+    ) -> Result<Self, Wgpu2dError> {
+        Wgpu2d::check_support(device, config, settings)?;
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let wgpu2d = Wgpu2d::with_shaders(device, config.format, settings, colored, textured);
+        match poll_now(device.pop_error_scope()) {
+            Some(Some(error)) => Err(Wgpu2dError::InvalidShader(error.to_string())),
+            _ => Ok(wgpu2d),
+        }
+    }
+
+    /// Returns an error if the format of the render targets isn't in [`SUPPORTED_FORMATS`],
+    /// or if `device` can't render to the stencil format.
+    fn check_support(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        settings: &Wgpu2dSettings,
+    ) -> Result<(), Wgpu2dError> {
+        let format = settings.target_format.unwrap_or(config.format);
+        if !SUPPORTED_FORMATS.contains(&format) {
+            return Err(Wgpu2dError::UnsupportedFormat(format));
+        }
+        let stencil_format = select_stencil_format(device, settings.depth_stencil_format);
+        if !is_stencil_renderable(device, stencil_format) {
+            return Err(Wgpu2dError::UnsupportedStencilFormat(stencil_format));
+        }
+        Ok(())
+    }
+
+    /// Creates a new `Wgpu2d` with `settings`.
+    pub fn with_settings<'b>(
+        device: &'a wgpu::Device,
+        config: &'b wgpu::SurfaceConfiguration,
+        settings: &Wgpu2dSettings,
+    ) -> Self {
+        Wgpu2d::with_format(device, config.format, settings)
+    }
+
+    /// Creates a new `Wgpu2d` rendering to textures of `format`, without a surface.
+    ///
+    /// This suits offscreen rendering, e.g. in tests without a window, with [`Wgpu2d::draw_to_texture`]
+    /// and [`Wgpu2d::capture`] to read the frame back.
+    pub fn new_headless(device: &'a wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        Wgpu2d::with_format(device, format, &Wgpu2dSettings::default())
+    }
+
+    /// Creates a new `Wgpu2d` with `settings`, rendering to `format` unless [`Wgpu2dSettings::target_format`] is set.
+    fn with_format(
+        device: &'a wgpu::Device,
+        format: wgpu::TextureFormat,
+        settings: &Wgpu2dSettings,
+    ) -> Self {
+        Wgpu2d::with_shaders(device, format, settings, None, None)
+    }
+
+    /// Creates a new `Wgpu2d` as [`Wgpu2d::with_format`] does, with the `colored` and `textured` shaders instead of the built-in ones.
+    fn with_shaders(
+        device: &'a wgpu::Device,
+        format: wgpu::TextureFormat,
+        settings: &Wgpu2dSettings,
+        colored: Option<wgpu::ShaderModuleDescriptor>,
+        textured: Option<wgpu::ShaderModuleDescriptor>,
+    ) -> Self {
+        let format = settings.target_format.map_or(format, |format| {
+            if is_renderable(device, format) {
+                format
+            } else {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            }
+        });
+
+        let stencil_format = select_stencil_format(device, settings.depth_stencil_format);
+        let depth_test = settings.depth_test && stencil_format.has_depth_aspect();
+        let depth_compare = if depth_test {
+            wgpu::CompareFunction::LessEqual
+        } else {
+            wgpu::CompareFunction::Always
+        };
+        let unclipped_depth = device
+            .features()
+            .contains(wgpu::Features::DEPTH_CLIP_CONTROL);
+        let conservative_rasterization = settings.conservative_rasterization
+            && device
+                .features()
+                .contains(wgpu::Features::CONSERVATIVE_RASTERIZATION);
+        // Only the "clip" and "increment" stencil states never pass, writing the stencil wherever they draw.
+        let conservative = |stencil: &wgpu::StencilState| {
+            conservative_rasterization && stencil.front.compare == wgpu::CompareFunction::Never
+        };
+        let mask_stencil = |stencil: wgpu::StencilState| wgpu::StencilState {
+            read_mask: stencil.read_mask & u32::from(settings.stencil_read_mask),
+            write_mask: stencil.write_mask & u32::from(settings.stencil_write_mask),
+            ..stencil
+        };
+        let anti_alias = if settings.accumulate {
+            AntiAlias::None
+        } else {
+            settings.anti_alias
+        };
+        let sample_count = match anti_alias {
+            AntiAlias::Msaa(count)
+                if supports_sample_count(device, format, stencil_format, count) =>
+            {
+                count
+            }
+            _ => 1,
+        };
+
+        let (colored_vertex_layout, textured_vertex_layout) = if settings.compact_vertices {
+            (
+                CompactColoredPipelineInput::desc(),
+                CompactTexturedPipelineInput::desc(),
+            )
+        } else {
+            (ColoredPipelineInput::desc(), TexturedPipelineInput::desc())
+        };
+
+        let colored_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Colored Pipeline Layout"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            });
+
+        let colored_shader_module =
+            device.create_shader_module(colored.unwrap_or(wgpu::include_wgsl!("colored.wgsl")));
+
+        let create_colored_pipeline_with_topology =
+            |label: &str,
+             topology: wgpu::PrimitiveTopology,
+             blend: Option<wgpu::BlendState>,
+             stencil: wgpu::StencilState,
+             write_mask: wgpu::ColorWrites| {
+                let (fragment_entry_point, blend) = if settings.premultiply_vertex_colors {
+                    ("fs_premultiplied", blend.map(premultiplied_blend))
+                } else {
+                    ("fs_main", blend)
+                };
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&colored_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &colored_shader_module,
+                        entry_point: "vs_main",
+                        buffers: std::slice::from_ref(&colored_vertex_layout),
+                    },
+                    primitive: wgpu::PrimitiveState {
+                        topology,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        unclipped_depth,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        conservative: conservative(&stencil),
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: stencil_format,
+                        depth_write_enabled: depth_test && !write_mask.is_empty(),
+                        depth_compare,
+                        stencil: mask_stencil(stencil),
+                        bias: settings.depth_bias,
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: sample_count,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &colored_shader_module,
+                        entry_point: fragment_entry_point,
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend,
+                            write_mask,
+                        })],
+                    }),
+                    multiview: None,
+                })
+            };
+
+        let create_colored_pipeline =
+            |label: &str,
+             blend: Option<wgpu::BlendState>,
+             stencil: wgpu::StencilState,
+             write_mask: wgpu::ColorWrites| {
+                create_colored_pipeline_with_topology(
+                    label,
+                    wgpu::PrimitiveTopology::TriangleList,
+                    blend,
+                    stencil,
+                    write_mask,
+                )
+            };
+
+        let colored_render_pipelines = PsoStencil::new(|blend, stencil| {
+            create_colored_pipeline(
+                "Colored Render Pipeline",
+                blend,
+                stencil,
+                wgpu::ColorWrites::ALL,
+            )
+        });
+
+        let line_render_pipelines = PsoStencil::new(|blend, stencil| {
+            create_colored_pipeline_with_topology(
+                "Line Render Pipeline",
+                wgpu::PrimitiveTopology::LineList,
+                blend,
+                stencil,
+                wgpu::ColorWrites::ALL,
+            )
+        });
+
+        let path_pipelines = PathPipelines::new(&create_colored_pipeline);
+
+        // Like the "increment" stencil state, but popping a level of nested clips.
+        let stencil_decrement = wgpu::StencilState {
+            front: wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::Never,
+                fail_op: wgpu::StencilOperation::DecrementClamp,
+                ..Default::default()
+            },
+            back: wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::Never,
+                fail_op: wgpu::StencilOperation::DecrementClamp,
+                ..Default::default()
+            },
+            read_mask: 255,
+            write_mask: 255,
+        };
+        let decrement_render_pipelines = PsoBlend::new(|blend| {
+            create_colored_pipeline(
+                "Decrement Render Pipeline",
+                blend,
+                stencil_decrement.clone(),
+                wgpu::ColorWrites::ALL,
+            )
+        });
+
+        let textured_bind_group_layout = Texture::create_bind_group_layout(device);
+
+        let textured_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Textured Pipeline Layout"),
+                bind_group_layouts: &[&textured_bind_group_layout],
                 push_constant_ranges: &[],
             });
 
-        let textured_shader_module =
-            device.create_shader_module(wgpu::include_wgsl!("textured.wgsl"));
+        let textured_shader_module =
+            device.create_shader_module(textured.unwrap_or(wgpu::include_wgsl!("textured.wgsl")));
+
+        let create_pipeline_with_vertex_layout =
+            |label: &str,
+             layout: &wgpu::PipelineLayout,
+             shader_module: &wgpu::ShaderModule,
+             vertex_layout: &wgpu::VertexBufferLayout,
+             fragment_entry_point: &str,
+             blend: Option<wgpu::BlendState>,
+             stencil: wgpu::StencilState| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(layout),
+                    vertex: wgpu::VertexState {
+                        module: shader_module,
+                        entry_point: "vs_main",
+                        buffers: std::slice::from_ref(vertex_layout),
+                    },
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        unclipped_depth,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        conservative: conservative(&stencil),
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: stencil_format,
+                        depth_write_enabled: depth_test,
+                        depth_compare,
+                        stencil: mask_stencil(stencil),
+                        bias: settings.depth_bias,
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: sample_count,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: shader_module,
+                        entry_point: fragment_entry_point,
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    multiview: None,
+                })
+            };
+
+        let create_textured_pipeline =
+            |label: &str,
+             layout: &wgpu::PipelineLayout,
+             shader_module: &wgpu::ShaderModule,
+             fragment_entry_point: &str,
+             blend: Option<wgpu::BlendState>,
+             stencil: wgpu::StencilState| {
+                create_pipeline_with_vertex_layout(
+                    label,
+                    layout,
+                    shader_module,
+                    &textured_vertex_layout,
+                    fragment_entry_point,
+                    blend,
+                    stencil,
+                )
+            };
+
+        let textured_render_pipelines = PsoStencil::new(|blend, stencil| {
+            let blend = if settings.premultiplied_alpha
+                && blend == Some(wgpu::BlendState::ALPHA_BLENDING)
+            {
+                Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING)
+            } else {
+                blend
+            };
+            let (fragment_entry_point, blend) =
+                match (settings.linear_tint, settings.premultiply_vertex_colors) {
+                    (false, false) => ("fs_main", blend),
+                    (true, false) => ("fs_linear_tint", blend),
+                    (false, true) => ("fs_premultiplied", blend.map(premultiplied_blend)),
+                    (true, true) => (
+                        "fs_linear_tint_premultiplied",
+                        blend.map(premultiplied_blend),
+                    ),
+                };
+            create_textured_pipeline(
+                "Textured Render Pipeline",
+                &textured_pipeline_layout,
+                &textured_shader_module,
+                fragment_entry_point,
+                blend,
+                stencil,
+            )
+        });
+
+        let premultiplied_render_pipelines = PsoStencilOnly::new(|stencil| {
+            create_textured_pipeline(
+                "Premultiplied Render Pipeline",
+                &textured_pipeline_layout,
+                &textured_shader_module,
+                "fs_premultiplied",
+                Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                stencil,
+            )
+        });
+
+        let sdf_shader_module = device.create_shader_module(wgpu::include_wgsl!("sdf.wgsl"));
+
+        let sdf_render_pipelines = PsoStencil::new(|blend, stencil| {
+            create_textured_pipeline(
+                "SDF Render Pipeline",
+                &textured_pipeline_layout,
+                &sdf_shader_module,
+                "fs_main",
+                blend,
+                stencil,
+            )
+        });
+
+        let gradient_pipelines = GradientPipelines::new(device, &create_textured_pipeline);
+
+        let arc_pipelines = ArcPipelines::new(device, &create_textured_pipeline);
+
+        let multitexture_pipelines = MultitexturePipelines::new(
+            device,
+            &textured_bind_group_layout,
+            &create_pipeline_with_vertex_layout,
+        );
+
+        let texture_array_pipelines = TextureArrayPipelines::new(device, &create_textured_pipeline);
+
+        let create_filter_sampler = |label, filter| {
+            device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some(label),
+                mag_filter: filter,
+                min_filter: filter,
+                mipmap_filter: filter,
+                ..Default::default()
+            })
+        };
+        let filter_samplers = [
+            create_filter_sampler("Nearest Sampler", wgpu::FilterMode::Nearest),
+            create_filter_sampler("Linear Sampler", wgpu::FilterMode::Linear),
+        ];
+
+        Self {
+            device,
+            format,
+            sample_count,
+            stencil_format,
+            depth_test,
+            conservative_rasterization,
+            colored_render_pipelines,
+            line_render_pipelines,
+            textured_render_pipelines,
+            premultiplied_render_pipelines,
+            sdf_render_pipelines,
+            path_pipelines,
+            decrement_render_pipelines,
+            gradient_pipelines,
+            arc_pipelines,
+            multitexture_pipelines,
+            texture_array_pipelines,
+            shadow_pipelines: ShadowPipelines::new(device, format, stencil_format, sample_count),
+            timer: if settings.gpu_timing
+                && device.features().contains(wgpu::Features::TIMESTAMP_QUERY)
+            {
+                Some(GpuTimer::new(device))
+            } else {
+                None
+            },
+            fxaa_pipeline: if anti_alias == AntiAlias::Fxaa {
+                Some(FxaaPipeline::new(device, format))
+            } else {
+                None
+            },
+            textured_bind_group_layout,
+            filter_samplers,
+            vertex_buffers: RefCell::new(VertexBuffers::new(
+                device,
+                settings.vertex_buffer_capacity,
+                settings.compact_vertices,
+            )),
+            stencil: RefCell::new(HashMap::new()),
+            multisampled: RefCell::new(HashMap::new()),
+            surface_size: [0, 0],
+            pixel_perfect_target: None,
+            accumulation_target: None,
+            mask_target: None,
+            settings: settings.clone(),
+            capture: Cell::new(Capture::Idle),
+        }
+    }
+
+    /// Returns the number of vertices the "colored" and "textured" vertex buffers can currently hold.
+    ///
+    /// The buffers are reused by every frame, and only grow, to the next power of two,
+    /// when a frame has more vertices.
+    pub fn vertex_buffer_capacity(&self) -> [u32; 2] {
+        self.vertex_buffers.borrow().capacity()
+    }
+
+    /// Rebuilds the pipelines and internal resources against `device`, with the settings `self` was created with.
+    ///
+    /// Call it after the previous device was lost, e.g. after a GPU reset or a driver update.
+    /// All handles created with the lost device become invalid, including every [`Texture`],
+    /// which must be created again through a [`TextureContext`] of the new device, from their source images.
+    pub fn recreate(&mut self, device: &'a wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        *self = Wgpu2d::with_settings(device, config, &self.settings);
+    }
+
+    /// Updates `self` for the surface configured with `config`, e.g. after a resize or a format change.
+    ///
+    /// The pipelines are rebuilt if the surface format changed, unless [`Wgpu2dSettings::target_format`] is set,
+    /// and the stencil and multisampled textures of the previous surface size are freed if the size changed.
+    /// Calling it every frame is cheap when nothing changed.
+    pub fn resize(&mut self, config: &wgpu::SurfaceConfiguration) {
+        if self.settings.target_format.is_none() && config.format != self.format {
+            let device = self.device;
+            self.recreate(device, config);
+            return;
+        }
+        let size = [config.width, config.height];
+        if size != self.surface_size {
+            self.stencil.get_mut().remove(&self.surface_size);
+            self.multisampled.get_mut().remove(&self.surface_size);
+            self.surface_size = size;
+        }
+    }
+
+    /// Returns a view of the stencil texture of `size`, which is only created the first time this size is drawn at.
+    ///
+    /// Views are cheap, unlike allocating the texture every frame.
+    fn stencil_view(&self, [width, height]: [u32; 2]) -> wgpu::TextureView {
+        let mut stencil = self.stencil.borrow_mut();
+        if stencil.len() >= MAX_CACHED_TEXTURE_SIZES && !stencil.contains_key(&[width, height]) {
+            stencil.clear();
+        }
+        let texture = stencil.entry([width, height]).or_insert_with(|| {
+            self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Stencil Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: self.sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.stencil_format,
+                // Not sampled, which on GL would make it incompatible with the multisampled color texture.
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[self.stencil_format],
+            })
+        });
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Stencil Texture View"),
+            ..Default::default()
+        })
+    }
+
+    /// Returns a view of the multisampled color texture of `size`, created the first time this size is drawn at,
+    /// or `None` without multisampling.
+    fn multisampled_view(&self, [width, height]: [u32; 2]) -> Option<wgpu::TextureView> {
+        if self.sample_count <= 1 {
+            return None;
+        }
+        let mut multisampled = self.multisampled.borrow_mut();
+        if multisampled.len() >= MAX_CACHED_TEXTURE_SIZES
+            && !multisampled.contains_key(&[width, height])
+        {
+            multisampled.clear();
+        }
+        let texture = multisampled.entry([width, height]).or_insert_with(|| {
+            self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Multisampled Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: self.sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+        });
+        Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+
+    /// Creates a bind group sampling `texture` with the shared sampler of `filter`.
+    fn filtered_bind_group(&self, texture: &Texture, filter: Filter) -> wgpu::BindGroup {
+        let sampler = match filter {
+            Filter::Nearest => &self.filter_samplers[0],
+            Filter::Linear => &self.filter_samplers[1],
+        };
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Filtered Texture Bind Group"),
+            layout: &self.textured_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Returns the format of the render targets the pipelines were built for.
+    ///
+    /// This is the surface format, unless [`Wgpu2dSettings::target_format`] was set,
+    /// in which case it is that format, or `Rgba8UnormSrgb` if the device can't render to it.
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// Returns the number of samples per pixel the pipelines were built with.
+    ///
+    /// This is the count of [`AntiAlias::Msaa`], or 1 without multisampling,
+    /// including when the device doesn't support that count for the format.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Returns `true` if the pipelines writing clip masks use conservative rasterization.
+    ///
+    /// This is false without [`Wgpu2dSettings::conservative_rasterization`],
+    /// including when the device doesn't have `wgpu::Features::CONSERVATIVE_RASTERIZATION`.
+    pub fn conservative_rasterization(&self) -> bool {
+        self.conservative_rasterization
+    }
+
+    /// Returns the format of the stencil buffers the pipelines were built for.
+    ///
+    /// This is `Depth24PlusStencil8`, or `Depth32FloatStencil8` if the device can't render to the former
+    /// and was requested with `DEPTH32FLOAT_STENCIL8`.
+    pub fn stencil_format(&self) -> wgpu::TextureFormat {
+        self.stencil_format
+    }
+
+    /// Performs 2D graphics operations and returns encoded commands.
+    ///
+    /// To actually draw on a window surface, you must [`submit`](`wgpu::Queue::submit`) the returned [`CommandBuffer`](`wgpu::CommandBuffer`).
+    /// The output is never cleared implicitly, only by `clear_color`, but it only keeps the previous frame
+    /// if the surface does so; [`Wgpu2dSettings::accumulate`] guarantees it.
+    pub fn draw<F>(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        output_view: &wgpu::TextureView,
+        viewport: Viewport,
+        f: F,
+    ) -> wgpu::CommandBuffer
+    where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        encode(device, |encoder| {
+            self.draw_with_encoder(device, config, encoder, output_view, viewport, f)
+        })
+    }
+
+    /// Performs 2D graphics operations and records them into `encoder`, instead of a command encoder of its own.
+    ///
+    /// This interleaves the 2D drawing with passes of your own, such as compute passes, in one submission.
+    /// Vertex uploads are recorded into `encoder` too. Otherwise, it is the same as [`Wgpu2d::draw`].
+    pub fn draw_with_encoder<F>(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        viewport: Viewport,
+        f: F,
+    ) where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        if self.settings.accumulate {
+            self.draw_accumulated(device, config, encoder, output_view, viewport, f);
+            return;
+        }
+        let mut g = WgpuGraphics::new(self, config);
+        let c = Context::new_viewport(viewport);
+        f(c, &mut g);
+        self.encode_into(device, encoder, |encoder| {
+            g.encode(device, encoder, output_view)
+        });
+    }
+
+    /// Performs 2D graphics operations in logical units, `scale_factor` physical pixels each, and returns encoded commands.
+    ///
+    /// This suits HiDPI displays, where the surface has `scale_factor` times more pixels than the logical size of the window.
+    /// The context lays out in logical units, and scissor rectangles of draw states are also given in logical units,
+    /// scaled to pixels with [`WgpuGraphics::set_scissor_scale`]. Otherwise, it is the same as [`Wgpu2d::draw`].
+    pub fn draw_scaled<F>(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        output_view: &wgpu::TextureView,
+        scale_factor: f64,
+        f: F,
+    ) -> wgpu::CommandBuffer
+    where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        let viewport = Viewport {
+            rect: [0, 0, config.width as i32, config.height as i32],
+            draw_size: [config.width, config.height],
+            window_size: [
+                config.width as f64 / scale_factor,
+                config.height as f64 / scale_factor,
+            ],
+        };
+        self.draw(device, config, output_view, viewport, |c, g| {
+            g.set_scissor_scale(scale_factor);
+            f(c, g)
+        })
+    }
+
+    /// Performs 2D graphics operations once per view of `views`, into the same frame, and returns encoded commands.
+    ///
+    /// `f` is called with the index of the view, and a context of the view's size, transformed by its camera.
+    /// Each view is drawn through the viewport of its rectangle, so e.g. a minimap can replay the scene scaled down,
+    /// sharing the code generating its geometry with the main view.
+    /// Scissor rectangles of draw states are still in pixels of the whole render target.
+    pub fn draw_multi_view<F>(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        output_view: &wgpu::TextureView,
+        views: &[ViewSpec],
+        mut f: F,
+    ) -> wgpu::CommandBuffer
+    where
+        F: FnMut(usize, Context, &mut WgpuGraphics),
+    {
+        let mut g = WgpuGraphics::new(self, config);
+        for (i, view) in views.iter().enumerate() {
+            let [x, y, width, height] = view.rect;
+            g.set_viewport_rect([x as f32, y as f32, width as f32, height as f32], 0.0..1.0);
+            let mut c = Context::new_viewport(Viewport {
+                rect: [0, 0, width as i32, height as i32],
+                draw_size: [width, height],
+                window_size: [width as f64, height as f64],
+            });
+            c.view = graphics::math::multiply(c.view, view.transform);
+            c.transform = c.view;
+            f(i, c, &mut g);
+        }
+        g.reset_viewport_rect();
+        g.draw(device, output_view)
+    }
+
+    /// Performs 2D graphics operations over the previous frame, kept in the accumulation texture,
+    /// and records commands copying the result onto `output_view` into `encoder`.
+    fn draw_accumulated<F>(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        viewport: Viewport,
+        f: F,
+    ) where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        let size = [config.width, config.height];
+        if self
+            .accumulation_target
+            .as_ref()
+            .is_none_or(|target| target.get_size() != (size[0], size[1]))
+        {
+            self.accumulation_target =
+                Some(self.create_render_target(device, size, &TextureSettings::new()));
+        }
+
+        let this = &*self;
+        let target = this.accumulation_target.as_ref().unwrap();
+        this.encode_into(device, encoder, |encoder| {
+            let mut g = WgpuGraphics::with_size(this, size);
+            f(Context::new_viewport(viewport), &mut g);
+            g.encode(device, encoder, &target.view);
+
+            let (xys, uvs) = texture_quad([-1.0, 1.0, 1.0, -1.0]);
+            let draw_state = DrawState {
+                scissor: None,
+                stencil: None,
+                blend: None,
+            };
+
+            let mut g = WgpuGraphics::new(this, config);
+            g.tri_list_uv(&draw_state, &[1.0; 4], target, |f| f(&xys, &uvs));
+            g.encode(device, encoder, output_view);
+        });
+    }
+
+    /// Performs 2D graphics operations, masked by the alpha channel of `mask`, and returns encoded commands.
+    ///
+    /// The operations are drawn into an offscreen texture of `config`'s size, cleared to transparent,
+    /// which is then drawn over `output_view` multiplied by `mask`, stretched over the whole target.
+    /// Masks are usually white, with the alpha channel varying.
+    /// Animating the mask gives scene transitions such as circle reveals.
+    pub fn draw_with_mask<F>(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        output_view: &wgpu::TextureView,
+        viewport: Viewport,
+        mask: &Texture,
+        f: F,
+    ) -> wgpu::CommandBuffer
+    where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        let size = [config.width, config.height];
+        if self
+            .mask_target
+            .as_ref()
+            .is_none_or(|target| target.get_size() != (size[0], size[1]))
+        {
+            self.mask_target =
+                Some(self.create_render_target(device, size, &TextureSettings::new()));
+        }
+
+        let this = &*self;
+        let target = this.mask_target.as_ref().unwrap();
+        this.encode(device, |encoder| {
+            let mut g = WgpuGraphics::with_size(this, size);
+            g.clear_color([0.0; 4]);
+            f(Context::new_viewport(viewport), &mut g);
+            g.encode(device, encoder, &target.view);
+
+            let (xys, uvs) = texture_quad([-1.0, 1.0, 1.0, -1.0]);
+            let draw_state = DrawState {
+                scissor: None,
+                stencil: None,
+                blend: Some(Blend::Alpha),
+            };
+
+            let mut g = WgpuGraphics::new(this, config);
+            g.draw_multitexture(
+                &draw_state,
+                target,
+                mask,
+                1.0,
+                &xys,
+                graphics::math::identity(),
+                &uvs,
+            );
+            g.encode(device, encoder, output_view);
+        })
+    }
+
+    /// Performs 2D graphics operations at a fixed resolution of `internal_size`, and returns encoded commands.
+    ///
+    /// The result is scaled up to the largest integer factor fitting in `config`'s size with nearest filtering,
+    /// and centered with black bars around it, which keeps pixel art crisp.
+    pub fn draw_pixel_perfect<F>(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        output_view: &wgpu::TextureView,
+        internal_size: [u32; 2],
+        f: F,
+    ) -> wgpu::CommandBuffer
+    where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        let [width, height] = internal_size;
+        if self
+            .pixel_perfect_target
+            .as_ref()
+            .is_none_or(|target| target.get_size() != (width, height))
+        {
+            self.pixel_perfect_target = Some(self.create_render_target(
+                device,
+                internal_size,
+                &TextureSettings::new().filter(Filter::Nearest),
+            ));
+        }
+
+        let this = &*self;
+        let target = this.pixel_perfect_target.as_ref().unwrap();
+        this.encode(device, |encoder| {
+            let mut g = WgpuGraphics::with_size(this, internal_size);
+            let c = Context::new_viewport(Viewport {
+                rect: [0, 0, width as i32, height as i32],
+                draw_size: internal_size,
+                window_size: [width as f64, height as f64],
+            });
+            f(c, &mut g);
+            g.encode(device, encoder, &target.view);
+
+            let [x, y, ..] = letterbox_rect(internal_size, [config.width, config.height], true);
+            let left = (2.0 * x / config.width as f64 - 1.0) as f32;
+            let top = (1.0 - 2.0 * y / config.height as f64) as f32;
+            let (xys, uvs) = texture_quad([left, top, -left, -top]);
+            let draw_state = DrawState {
+                scissor: None,
+                stencil: None,
+                blend: None,
+            };
+
+            let mut g = WgpuGraphics::new(this, config);
+            g.clear_color([0.0, 0.0, 0.0, 1.0]);
+            g.tri_list_uv(&draw_state, &[1.0; 4], target, |f| f(&xys, &uvs));
+            g.encode(device, encoder, output_view);
+        })
+    }
+
+    /// Creates a texture of `size` which can be drawn into with [`Wgpu2d::draw_to_texture`], and sampled with `settings`.
+    ///
+    /// wgpu only supports transparent black, opaque black and opaque white border colors,
+    /// so other border colors of `settings` are approximated with the nearest of them.
+    pub fn create_render_target(
+        &self,
+        device: &wgpu::Device,
+        [width, height]: [u32; 2],
+        settings: &TextureSettings,
+    ) -> Texture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Render Target Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let sampler = Texture::create_sampler(device, settings, 1);
+        Texture::from_wgpu(device, texture, [width, height], Arc::new(sampler))
+    }
+
+    /// Creates a render target of `size` which can be drawn into with [`Wgpu2d::draw_to_multisampled_target`],
+    /// with the sample count of [`Wgpu2dSettings::anti_alias`], and resolved into a texture sampled with `settings`.
+    pub fn create_multisampled_target(
+        &self,
+        device: &wgpu::Device,
+        [width, height]: [u32; 2],
+        settings: &TextureSettings,
+    ) -> MultisampledTarget {
+        let multisampled = if self.sample_count > 1 {
+            Some(device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Multisampled Render Target Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: self.sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            }))
+        } else {
+            None
+        };
+        MultisampledTarget {
+            multisampled,
+            resolved: self.create_render_target(device, [width, height], settings),
+        }
+    }
+
+    /// Performs 2D graphics operations onto the multisampled texture of `target`,
+    /// resolves it into the resolved texture, and returns encoded commands.
+    ///
+    /// Returns an error if `target` wasn't created with the format and sample count of the pipelines.
+    pub fn draw_to_multisampled_target<F>(
+        &self,
+        device: &wgpu::Device,
+        target: &MultisampledTarget,
+        f: F,
+    ) -> Result<wgpu::CommandBuffer, TextureError>
+    where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        let resolved = &target.resolved;
+        if resolved.texture.format() != self.format {
+            return Err(TextureError::IncompatibleFormat(resolved.texture.format()));
+        }
+        let sample_count = target
+            .multisampled
+            .as_ref()
+            .map_or(1, |multisampled| multisampled.sample_count());
+        if sample_count != self.sample_count {
+            return Err(TextureError::IncompatibleSampleCount(sample_count));
+        }
+
+        let (width, height) = resolved.get_size();
+        Ok(self.encode(device, |encoder| {
+            let multisampled_view = target.multisampled.as_ref().map(|multisampled| {
+                multisampled.create_view(&wgpu::TextureViewDescriptor::default())
+            });
+            let mut g =
+                WgpuGraphics::with_multisampled_view(self, [width, height], multisampled_view);
+            g.keep_multisampled = true;
+            let c = Context::new_viewport(Viewport {
+                rect: [0, 0, width as i32, height as i32],
+                draw_size: [width, height],
+                window_size: [width as f64, height as f64],
+            });
+            f(c, &mut g);
+            g.encode(device, encoder, &resolved.view);
+        }))
+    }
+
+    /// Performs 2D graphics operations onto `target`, and returns encoded commands.
+    ///
+    /// Returns an error if `target` isn't a render target of the format of the pipelines,
+    /// as created by [`Wgpu2d::create_render_target`].
+    pub fn draw_to_texture<F>(
+        &self,
+        device: &wgpu::Device,
+        target: &Texture,
+        f: F,
+    ) -> Result<wgpu::CommandBuffer, TextureError>
+    where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        if !target.is_render_target() {
+            return Err(TextureError::NotRenderTarget);
+        }
+        if target.texture.format() != self.format {
+            return Err(TextureError::IncompatibleFormat(target.texture.format()));
+        }
+
+        let (width, height) = target.get_size();
+        Ok(self.draw_to_view(device, &target.view, [width, height], f))
+    }
+
+    /// Performs 2D graphics operations onto the array `layer` of `target`, and returns encoded commands.
+    ///
+    /// This fills texture arrays, such as atlases, one layer at a time.
+    /// Returns an error if `target` isn't a 2D texture or texture array with `RENDER_ATTACHMENT` usage
+    /// and the format of the pipelines, or if it has no such layer.
+    /// Slices of 3D textures can't be drawn into, as their views can't be render attachments.
+    pub fn draw_to_texture_layer<F>(
+        &self,
+        device: &wgpu::Device,
+        target: &wgpu::Texture,
+        layer: u32,
+        f: F,
+    ) -> Result<wgpu::CommandBuffer, TextureError>
+    where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        if !target
+            .usage()
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+        {
+            return Err(TextureError::NotRenderTarget);
+        }
+        if target.format() != self.format {
+            return Err(TextureError::IncompatibleFormat(target.format()));
+        }
+        if target.dimension() != wgpu::TextureDimension::D2 {
+            return Err(TextureError::UnsupportedDimension(target.dimension()));
+        }
+        if layer >= target.depth_or_array_layers() {
+            return Err(TextureError::InvalidLayer(layer));
+        }
+
+        let view = target.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Render Target Layer View"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+        Ok(self.draw_to_view(device, &view, [target.width(), target.height()], f))
+    }
+
+    /// Performs 2D graphics operations onto `view` of `size`, and returns encoded commands.
+    ///
+    /// Unlike [`Wgpu2d::draw`], which draws onto the surface, the stencil buffer and viewport match `size`,
+    /// so this draws onto any render attachment of the format of the pipelines, such as an offscreen texture.
+    pub fn draw_to_view<F>(
+        &self,
+        device: &wgpu::Device,
+        view: &wgpu::TextureView,
+        [width, height]: [u32; 2],
+        f: F,
+    ) -> wgpu::CommandBuffer
+    where
+        F: FnOnce(Context, &mut WgpuGraphics),
+    {
+        self.encode(device, |encoder| {
+            let mut g = WgpuGraphics::with_size(self, [width, height]);
+            let c = Context::new_viewport(Viewport {
+                rect: [0, 0, width as i32, height as i32],
+                draw_size: [width, height],
+                window_size: [width as f64, height as f64],
+            });
+            f(c, &mut g);
+            g.encode(device, encoder, view);
+        })
+    }
+
+    /// Reads back the top left `size` rectangle of `source`, waiting for the GPU to finish the pending work,
+    /// and saves it as a PNG file at `path`.
+    ///
+    /// `source` must have `COPY_SRC` usage, which surface textures only have if the surface was configured with it.
+    /// Its format must be 8-bit RGBA or BGRA; BGRA pixels are swizzled to RGBA.
+    pub fn save_frame_png<P>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &wgpu::Texture,
+        size: [u32; 2],
+        path: P,
+    ) -> Result<(), TextureError>
+    where
+        P: AsRef<Path>,
+    {
+        Wgpu2d::read_frame(device, queue, source, size)?
+            .save(path)
+            .map_err(TextureError::ImageError)
+    }
+
+    /// Reads back the whole `source`, waiting for the GPU to finish the pending work, e.g. to compare
+    /// frames rendered offscreen against reference images in tests.
+    ///
+    /// `source` must have `COPY_SRC` usage, as render targets created by [`Wgpu2d::create_render_target`] have.
+    /// Its format must be 8-bit RGBA or BGRA; BGRA pixels are swizzled to RGBA.
+    pub fn capture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &wgpu::Texture,
+    ) -> Result<image::RgbaImage, TextureError> {
+        let size = source.size();
+        Wgpu2d::read_frame(device, queue, source, [size.width, size.height])
+    }
+
+    /// Reads back the top left `size` rectangle of `source`, waiting for the GPU to finish the pending work.
+    fn read_frame(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &wgpu::Texture,
+        [width, height]: [u32; 2],
+    ) -> Result<image::RgbaImage, TextureError> {
+        use wgpu::TextureFormat;
+
+        let bgra = match source.format() {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            format => return Err(TextureError::UnreadableFormat(format)),
+        };
+        let source_size = source.size();
+        if width == 0 || height == 0 || width > source_size.width || height > source_size.height {
+            return Err(TextureError::OutOfBounds {
+                offset: [0, 0],
+                size: [width, height],
+            });
+        }
+
+        let row_size = 4 * width as usize;
+        let padded_row_size = loader::padded_row_size(width);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame Read Buffer"),
+            size: (padded_row_size * height as usize) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        queue.submit(Some(encode(device, |encoder| {
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture: source,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded_row_size as u32),
+                        rows_per_image: Some(height),
+                    },
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            )
+        })));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .unwrap_or(Err(wgpu::BufferAsyncError))
+            .map_err(TextureError::ReadFailed)?;
+
+        let mut data = Vec::with_capacity(row_size * height as usize);
+        for padded_row in slice.get_mapped_range().chunks(padded_row_size) {
+            data.extend_from_slice(&padded_row[..row_size]);
+        }
+        buffer.unmap();
+        if bgra {
+            for pixel in data.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Ok(image::RgbaImage::from_raw(width, height, data).unwrap())
+    }
+
+    /// Calls `callback` once the GPU has finished all the work submitted to `queue` so far.
+    ///
+    /// Call it right after submitting a frame to know when that frame is done,
+    /// e.g. to time frames or to reuse CPU memory a texture was uploaded from.
+    /// Vertex buffers are written by copies recorded into the frame, so they don't need this to be reused.
+    pub fn on_frame_complete<F>(&self, queue: &wgpu::Queue, callback: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        queue.on_submitted_work_done(callback);
+    }
+
+    /// Returns encoded commands recorded by `f`.
+    fn encode<F>(&self, device: &wgpu::Device, f: F) -> wgpu::CommandBuffer
+    where
+        F: FnOnce(&mut wgpu::CommandEncoder),
+    {
+        encode(device, |encoder| self.encode_into(device, encoder, f))
+    }
+
+    /// Records the commands of `f` into `encoder`, as [`Wgpu2d::encode`] does into a command encoder of its own.
+    fn encode_into<F>(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, f: F)
+    where
+        F: FnOnce(&mut wgpu::CommandEncoder),
+    {
+        // The previous frame was submitted by now, so its capture can end.
+        let label = match self.capture.replace(Capture::Idle) {
+            Capture::Idle => None,
+            Capture::Requested(label) => {
+                device.start_capture();
+                self.capture.set(Capture::Capturing);
+                Some(label)
+            }
+            Capture::Capturing => {
+                device.stop_capture();
+                None
+            }
+        };
+
+        match label {
+            Some(label) => {
+                encoder.push_debug_group(label);
+                f(encoder);
+                encoder.pop_debug_group();
+            }
+            None => f(encoder),
+        }
+        if let Some(timer) = &self.timer {
+            timer.resolve(encoder);
+        }
+    }
+
+    /// Captures the next frame with the graphics debugger attached to the device, such as RenderDoc,
+    /// with its commands grouped under `label`.
+    ///
+    /// The capture starts when the next frame is encoded, and stops when the frame after it is,
+    /// so it covers the submission of the captured frame.
+    /// It does nothing on back-ends without capture support.
+    pub fn capture_next_frame(&self, label: &'static str) {
+        self.capture.set(Capture::Requested(label));
+    }
+
+    /// Returns the GPU time of each render pass of the last drawn frame, in the order they were encoded,
+    /// with [`Wgpu2dSettings::gpu_timing`].
+    ///
+    /// The frame must have been submitted to `queue`; this waits for the GPU to finish it.
+    /// It is empty without timing, and at most 64 passes are timed per frame.
+    pub fn gpu_timings(
+        &self,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<PassTiming>, wgpu::BufferAsyncError> {
+        match &self.timer {
+            Some(timer) => timer.read(self.device, queue),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+/// State of the capture requested by [`Wgpu2d::capture_next_frame`].
+#[derive(Clone, Copy)]
+enum Capture {
+    Idle,
+    Requested(&'static str),
+    Capturing,
+}
+
+/// Pipeline and bindings used to draw a [`Batch`].
+#[derive(Clone)]
+enum BatchKind {
+    Colored,
+    Lines,
+    Textured(Arc<wgpu::BindGroup>),
+    Premultiplied(Arc<wgpu::BindGroup>),
+    Sdf(Arc<wgpu::BindGroup>),
+    PathFill(FillRule),
+    PathCover,
+    StencilDecrement,
+    Gradient(Arc<wgpu::BindGroup>),
+    CircleArc(Arc<wgpu::BindGroup>),
+    Multitexture(Arc<wgpu::BindGroup>, Arc<wgpu::BindGroup>),
+    TextureArray(Arc<wgpu::BindGroup>),
+}
+
+impl BatchKind {
+    /// Returns `true` if vertices of `self` and `other` can be drawn in one call.
+    fn is_compatible(&self, other: &BatchKind) -> bool {
+        match (self, other) {
+            (BatchKind::Colored, BatchKind::Colored)
+            | (BatchKind::Lines, BatchKind::Lines)
+            | (BatchKind::StencilDecrement, BatchKind::StencilDecrement) => true,
+            (BatchKind::Textured(a), BatchKind::Textured(b))
+            | (BatchKind::Premultiplied(a), BatchKind::Premultiplied(b))
+            | (BatchKind::Sdf(a), BatchKind::Sdf(b))
+            | (BatchKind::TextureArray(a), BatchKind::TextureArray(b)) => Arc::ptr_eq(a, b),
+            // Every path needs its own fill and cover.
+            _ => false,
+        }
+    }
+}
+
+/// Viewport rectangle set by [`WgpuGraphics::set_viewport_rect`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ViewportRect {
+    rect: [f32; 4],
+    depth_range: [f32; 2],
+}
+
+/// A range of vertices drawn with the same pipeline, bindings, draw state and viewport.
+#[derive(Clone)]
+struct Batch {
+    kind: BatchKind,
+    draw_state: DrawState,
+    viewport: Option<ViewportRect>,
+    vertices: Range<u32>,
+    /// Range of the index buffer drawn instead of `vertices`, for indexed draws.
+    ///
+    /// Indices are offset to point into the whole vertex buffer of the batch.
+    indices: Option<Range<u32>>,
+    /// Debug group the draw call is wrapped in, set by [`WgpuGraphics::set_debug_label`].
+    label: Option<&'static str>,
+}
+
+impl Batch {
+    /// Returns `true` if the batch writes the stencil buffer.
+    fn writes_stencil(&self) -> bool {
+        match self.kind {
+            BatchKind::PathFill(_) | BatchKind::PathCover | BatchKind::StencilDecrement => true,
+            _ => matches!(
+                self.draw_state.stencil,
+                Some(Stencil::Clip(_)) | Some(Stencil::Increment)
+            ),
+        }
+    }
+
+    /// Returns `true` if the batch is drawn with the stencil reference value of its draw state.
+    fn has_stencil_reference(&self) -> bool {
+        match self.kind {
+            BatchKind::Colored
+            | BatchKind::Lines
+            | BatchKind::Textured(_)
+            | BatchKind::Premultiplied(_)
+            | BatchKind::Sdf(_)
+            | BatchKind::Gradient(_)
+            | BatchKind::CircleArc(_)
+            | BatchKind::Multitexture(..)
+            | BatchKind::TextureArray(_) => stencil_reference(self.draw_state.stencil).is_some(),
+            BatchKind::PathFill(_) | BatchKind::PathCover | BatchKind::StencilDecrement => false,
+        }
+    }
+}
+
+/// A blurred shadow of `batches`, drawn before them.
+struct Shadow {
+    batches: Vec<Batch>,
+    offset: [f32; 2],
+    blur: f32,
+    color: Color,
+}
+
+/// `batches` rendered into a layer, which is drawn with its opacity multiplied by `alpha`.
+struct Group {
+    batches: Vec<Batch>,
+    alpha: f32,
+}
+
+/// An operation recorded by [`WgpuGraphics`], replayed in order when drawing.
+enum Command {
+    Draw(Batch),
+    Shadow(Shadow),
+    Group(Group),
+    /// Ends the current render pass.
+    Flush,
+    /// Ends the current render pass, and clears the depth to this value at the start of the next one.
+    ClearDepth(f32),
+}
+
+/// Graphics back-end.
+pub struct WgpuGraphics<'a> {
+    wgpu2d: &'a Wgpu2d<'a>,
+    width: u32,
+    height: u32,
+    clear_color: Option<Color>,
+    clear_stencil: Option<u8>,
+    stencil_view: wgpu::TextureView,
+    /// Multisampled texture the frame is drawn into and resolved from, with multisampling.
+    multisampled_view: Option<wgpu::TextureView>,
+    /// Whether the contents of `multisampled_view` are stored after being resolved.
+    keep_multisampled: bool,
+    viewport: Option<ViewportRect>,
+    scissor_scale: f64,
+    depth: Option<f32>,
+    debug_label: Option<&'static str>,
+    blend_constant: Color,
+    alpha_test: Option<f32>,
+    premultiplied: bool,
+    filter: Option<Filter>,
+    /// Bind groups of textures drawn with `filter`, by address of their own bind group, which is kept alive.
+    filtered_bind_groups: HashMap<usize, (Arc<wgpu::BindGroup>, Arc<wgpu::BindGroup>)>,
+    group_by_state: bool,
+    invalid_vertices: Vec<InvalidVertex>,
+    colored_data: Vec<ColoredPipelineInput>,
+    textured_data: Vec<TexturedPipelineInput>,
+    multitexture_data: Vec<MultitexturePipelineInput>,
+    index_data: Vec<u32>,
+    commands: Vec<Command>,
+}
 
-        let textured_render_pipelines = PsoStencil::new(|blend, stencil| {
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Textured Render Pipeline"),
-                layout: Some(&textured_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &textured_shader_module,
-                    entry_point: "vs_main",
-                    buffers: &[TexturedPipelineInput::desc()],
-                },
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: None,
-                    unclipped_depth: true,
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    conservative: false,
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth24PlusStencil8,
-                    depth_write_enabled: false,
-                    depth_compare: wgpu::CompareFunction::Always,
-                    stencil,
-                    bias: wgpu::DepthBiasState::default(),
+impl<'a> WgpuGraphics<'a> {
+    /// Creates a new `WgpuGraphics`.
+    pub fn new(wgpu2d: &'a Wgpu2d<'a>, config: &wgpu::SurfaceConfiguration) -> Self {
+        WgpuGraphics::with_size(wgpu2d, [config.width, config.height])
+    }
+
+    /// Creates a new `WgpuGraphics` drawing onto a render target of `size`.
+    fn with_size(wgpu2d: &'a Wgpu2d<'a>, size: [u32; 2]) -> Self {
+        WgpuGraphics::with_multisampled_view(wgpu2d, size, wgpu2d.multisampled_view(size))
+    }
+
+    /// Creates a new `WgpuGraphics` drawing onto a render target of `size`,
+    /// through `multisampled_view` with multisampling.
+    fn with_multisampled_view(
+        wgpu2d: &'a Wgpu2d<'a>,
+        [width, height]: [u32; 2],
+        multisampled_view: Option<wgpu::TextureView>,
+    ) -> Self {
+        let stencil_view = wgpu2d.stencil_view([width, height]);
+        Self {
+            wgpu2d,
+            width,
+            height,
+            clear_color: None,
+            clear_stencil: None,
+            stencil_view,
+            multisampled_view,
+            keep_multisampled: false,
+            viewport: None,
+            scissor_scale: 1.0,
+            depth: None,
+            debug_label: None,
+            blend_constant: [1.0; 4],
+            alpha_test: None,
+            premultiplied: false,
+            filter: None,
+            filtered_bind_groups: HashMap::new(),
+            group_by_state: false,
+            invalid_vertices: vec![],
+            colored_data: vec![],
+            textured_data: vec![],
+            multitexture_data: vec![],
+            index_data: vec![],
+            commands: vec![],
+        }
+    }
+
+    /// Performs 2D graphics operations and returns encoded commands.
+    ///
+    /// To actually draw on a window surface, you must [`submit`](`wgpu::Queue::submit`) the returned [`CommandBuffer`](`wgpu::CommandBuffer`).
+    /// Vertex buffers are shared between frames, so it must be submitted before drawing the next frame.
+    pub fn draw(
+        self,
+        device: &wgpu::Device,
+        output_view: &wgpu::TextureView,
+    ) -> wgpu::CommandBuffer {
+        self.wgpu2d
+            .encode(device, |encoder| self.encode(device, encoder, output_view))
+    }
+
+    /// Encodes the recorded operations onto `output_view`.
+    fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+    ) {
+        match &self.wgpu2d.fxaa_pipeline {
+            Some(fxaa_pipeline) => fxaa_pipeline.encode(
+                device,
+                encoder,
+                output_view,
+                [self.width, self.height],
+                |encoder, frame_view| self.encode_frame(device, encoder, frame_view),
+            ),
+            None => self.encode_frame(device, encoder, output_view),
+        }
+    }
+
+    /// Encodes the recorded operations onto `output_view`, resolving them to it with multisampling.
+    fn encode_frame(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+    ) {
+        let color_view = self.multisampled_view.as_ref().unwrap_or(output_view);
+        let mut color_load = match self.clear_color {
+            Some(c) => wgpu::LoadOp::Clear(to_wgpu_color(c)),
+            None => wgpu::LoadOp::Load,
+        };
+        // The stencil texture is reused across frames, so it starts cleared unless the frame clears it to another value.
+        let mut stencil_load = wgpu::LoadOp::Clear(self.clear_stencil.unwrap_or(0) as u32);
+        let mut depth_load = wgpu::LoadOp::Clear(1.0);
+
+        let mut vertex_buffers = self.wgpu2d.vertex_buffers.borrow_mut();
+        vertex_buffers.upload(
+            device,
+            encoder,
+            &self.colored_data,
+            &self.textured_data,
+            &self.multitexture_data,
+            &self.index_data,
+        );
+
+        // Draws are encoded into as few render passes as possible,
+        // splitting only where a shadow or a group needs its own passes, or where flushed.
+        let mut commands = &self.commands[..];
+        loop {
+            let end = commands
+                .iter()
+                .position(|command| !matches!(command, Command::Draw(_)))
+                .unwrap_or(commands.len());
+            let batches = commands[..end]
+                .iter()
+                .filter_map(|command| match command {
+                    Command::Draw(batch) => Some(batch),
+                    Command::Shadow(_)
+                    | Command::Group(_)
+                    | Command::Flush
+                    | Command::ClearDepth(_) => None,
+                })
+                .collect::<Vec<_>>();
+            let batches = if self.group_by_state {
+                group_batches(batches)
+            } else {
+                batches
+            };
+            self.encode_pass(encoder, "Draws", |encoder| {
+                self.encode_batches(
+                    encoder,
+                    color_view,
+                    None,
+                    color_load,
+                    &self.stencil_view,
+                    stencil_load,
+                    depth_load,
+                    &vertex_buffers,
+                    batches,
+                )
+            });
+            color_load = wgpu::LoadOp::Load;
+            stencil_load = wgpu::LoadOp::Load;
+            depth_load = wgpu::LoadOp::Load;
+
+            match commands.get(end) {
+                Some(Command::Shadow(shadow)) => self.encode_pass(encoder, "Shadow", |encoder| {
+                    self.encode_shadow(device, encoder, color_view, &vertex_buffers, shadow)
                 }),
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &textured_shader_module,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend,
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
+                Some(Command::Group(group)) => self.encode_pass(encoder, "Group", |encoder| {
+                    self.encode_group(device, encoder, color_view, &vertex_buffers, group)
                 }),
-                multiview: None,
-            })
+                Some(Command::Flush) => {}
+                Some(&Command::ClearDepth(value)) => depth_load = wgpu::LoadOp::Clear(value),
+                _ => break,
+            }
+            commands = &commands[end + 1..];
+        }
+
+        if let Some(multisampled_view) = &self.multisampled_view {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Resolve Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: multisampled_view,
+                    resolve_target: Some(output_view),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: self.keep_multisampled,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+        }
+    }
+
+    /// Encodes the passes of `f` in a debug group named `label`, timed with [`Wgpu2dSettings::gpu_timing`].
+    fn encode_pass<F>(&self, encoder: &mut wgpu::CommandEncoder, label: &'static str, f: F)
+    where
+        F: FnOnce(&mut wgpu::CommandEncoder),
+    {
+        encoder.push_debug_group(label);
+        match &self.wgpu2d.timer {
+            Some(timer) => timer.time(encoder, label, f),
+            None => f(encoder),
+        }
+        encoder.pop_debug_group();
+    }
+
+    /// Encodes a render pass drawing `batches`.
+    #[allow(clippy::too_many_arguments)]
+    fn encode_batches<'b, I>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        color_view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        color_load: wgpu::LoadOp<wgpu::Color>,
+        stencil_view: &wgpu::TextureView,
+        stencil_load: wgpu::LoadOp<u32>,
+        depth_load: wgpu::LoadOp<f32>,
+        vertex_buffers: &VertexBuffers,
+        batches: I,
+    ) where
+        I: IntoIterator<Item = &'b Batch>,
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: color_view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: color_load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: stencil_view,
+                depth_ops: if self.wgpu2d.depth_test {
+                    Some(wgpu::Operations {
+                        load: depth_load,
+                        store: true,
+                    })
+                } else {
+                    None
+                },
+                stencil_ops: Some(wgpu::Operations {
+                    load: stencil_load,
+                    store: true,
+                }),
+            }),
         });
 
-        Self {
+        let [r, g, b, a] = self.blend_constant;
+        render_pass.set_blend_constant(wgpu::Color {
+            r: r as f64,
+            g: g as f64,
+            b: b as f64,
+            a: a as f64,
+        });
+
+        render_pass.set_index_buffer(
+            vertex_buffers.indices.buffer.slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+
+        for batch in batches {
+            let [x, y, width, height] = match batch.draw_state.scissor {
+                Some(rect) => clamp_scissor(rect, [self.width, self.height]),
+                None => [0, 0, self.width, self.height],
+            };
+            // wgpu rejects scissors outside the render target, and nothing is drawn in an empty one.
+            if width == 0 || height == 0 {
+                continue;
+            }
+            let DrawState { stencil, blend, .. } = batch.draw_state;
+            let (pipeline, stencil_val) = match batch.kind {
+                BatchKind::Colored => self
+                    .wgpu2d
+                    .colored_render_pipelines
+                    .stencil_blend(stencil, blend),
+                BatchKind::Lines => self
+                    .wgpu2d
+                    .line_render_pipelines
+                    .stencil_blend(stencil, blend),
+                BatchKind::Textured(_) => self
+                    .wgpu2d
+                    .textured_render_pipelines
+                    .stencil_blend(stencil, blend),
+                BatchKind::Premultiplied(_) => {
+                    self.wgpu2d.premultiplied_render_pipelines.stencil(stencil)
+                }
+                BatchKind::Sdf(_) => self
+                    .wgpu2d
+                    .sdf_render_pipelines
+                    .stencil_blend(stencil, blend),
+                BatchKind::PathFill(fill_rule) => {
+                    (self.wgpu2d.path_pipelines.fill(fill_rule), None)
+                }
+                BatchKind::PathCover => (self.wgpu2d.path_pipelines.cover(blend), Some(0)),
+                BatchKind::StencilDecrement => {
+                    (self.wgpu2d.decrement_render_pipelines.blend(blend), None)
+                }
+                BatchKind::Gradient(_) => {
+                    self.wgpu2d.gradient_pipelines.stencil_blend(stencil, blend)
+                }
+                BatchKind::CircleArc(_) => self.wgpu2d.arc_pipelines.stencil_blend(stencil, blend),
+                BatchKind::Multitexture(..) => self
+                    .wgpu2d
+                    .multitexture_pipelines
+                    .stencil_blend(stencil, blend),
+                BatchKind::TextureArray(_) => self
+                    .wgpu2d
+                    .texture_array_pipelines
+                    .stencil_blend(stencil, blend),
+            };
+
+            let ViewportRect {
+                rect: [vx, vy, vw, vh],
+                depth_range: [min_depth, max_depth],
+            } = batch.viewport.unwrap_or(ViewportRect {
+                rect: [0.0, 0.0, self.width as f32, self.height as f32],
+                depth_range: [0.0, 1.0],
+            });
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_viewport(vx, vy, vw, vh, min_depth, max_depth);
+            render_pass.set_scissor_rect(x, y, width, height);
+            if let Some(stencil_val) = stencil_val {
+                render_pass.set_stencil_reference(stencil_val as u32);
+            }
+            match batch.kind {
+                BatchKind::Colored
+                | BatchKind::Lines
+                | BatchKind::PathFill(_)
+                | BatchKind::PathCover
+                | BatchKind::StencilDecrement => {
+                    render_pass.set_vertex_buffer(0, vertex_buffers.colored.buffer.slice(..));
+                }
+                BatchKind::Textured(ref bind_group)
+                | BatchKind::Premultiplied(ref bind_group)
+                | BatchKind::Sdf(ref bind_group)
+                | BatchKind::Gradient(ref bind_group)
+                | BatchKind::CircleArc(ref bind_group)
+                | BatchKind::TextureArray(ref bind_group) => {
+                    render_pass.set_bind_group(0, bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, vertex_buffers.textured.buffer.slice(..));
+                }
+                BatchKind::Multitexture(ref base, ref second) => {
+                    render_pass.set_bind_group(0, base, &[]);
+                    render_pass.set_bind_group(1, second, &[]);
+                    render_pass.set_vertex_buffer(0, vertex_buffers.multitexture.buffer.slice(..));
+                }
+            }
+            if let Some(label) = batch.label {
+                render_pass.push_debug_group(label);
+            }
+            match batch.indices {
+                Some(ref indices) => render_pass.draw_indexed(indices.clone(), 0, 0..1),
+                None => render_pass.draw(batch.vertices.clone(), 0..1),
+            }
+            if batch.label.is_some() {
+                render_pass.pop_debug_group();
+            }
+        }
+    }
+
+    /// Encodes the render passes drawing `shadow` onto `output_view`.
+    ///
+    /// The shape casting the shadow is rendered with a stencil buffer of its own, cleared to 0.
+    fn encode_shadow(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        vertex_buffers: &VertexBuffers,
+        shadow: &Shadow,
+    ) {
+        self.wgpu2d.shadow_pipelines.encode(
             device,
-            colored_render_pipelines,
-            textured_render_pipelines,
+            encoder,
+            output_view,
+            [self.width, self.height],
+            shadow.offset,
+            shadow.blur,
+            shadow.color,
+            |encoder, layer_view, resolve_target, stencil_view| {
+                self.encode_batches(
+                    encoder,
+                    layer_view,
+                    resolve_target,
+                    wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    stencil_view,
+                    wgpu::LoadOp::Clear(0),
+                    wgpu::LoadOp::Clear(1.0),
+                    vertex_buffers,
+                    &shadow.batches,
+                )
+            },
+        );
+    }
+
+    /// Encodes the render passes drawing `group` onto `output_view`.
+    ///
+    /// The layer is rendered with a stencil buffer of its own, cleared to 0.
+    fn encode_group(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        vertex_buffers: &VertexBuffers,
+        group: &Group,
+    ) {
+        self.wgpu2d.shadow_pipelines.encode_opacity(
+            device,
+            encoder,
+            output_view,
+            [self.width, self.height],
+            group.alpha,
+            |encoder, layer_view, resolve_target, stencil_view| {
+                self.encode_batches(
+                    encoder,
+                    layer_view,
+                    resolve_target,
+                    wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    stencil_view,
+                    wgpu::LoadOp::Clear(0),
+                    wgpu::LoadOp::Clear(1.0),
+                    vertex_buffers,
+                    &group.batches,
+                )
+            },
+        );
+    }
+
+    /// Sets whether draws are grouped by pipeline, bindings and draw state before being encoded.
+    ///
+    /// This avoids switching pipelines back and forth, e.g. in UIs toggling the blend mode per widget,
+    /// but breaks the painter's order between draws of different groups: a later draw may end up below an earlier one.
+    /// Draws are never moved across a draw writing the stencil buffer, a shadow or a flush, so clipping is kept.
+    /// It is disabled by default.
+    pub fn set_group_by_state(&mut self, group_by_state: bool) {
+        self.group_by_state = group_by_state;
+    }
+
+    /// Returns the vertices with a NaN or infinite position found so far, whose draws were skipped.
+    ///
+    /// It is always empty unless [`Wgpu2dSettings::debug_validate`] is set.
+    pub fn invalid_vertices(&self) -> &[InvalidVertex] {
+        &self.invalid_vertices
+    }
+
+    /// Returns counts of the work recorded so far, e.g. to check how the draw order splits batches.
+    ///
+    /// Draw calls are counted as recorded, before [`WgpuGraphics::set_group_by_state`] regroups them,
+    /// and include those of shadows and groups.
+    pub fn stats(&self) -> FrameStats {
+        let mut stats = FrameStats {
+            colored_vertices: self.colored_data.len(),
+            textured_vertices: self.textured_data.len(),
+            ..FrameStats::default()
+        };
+        let mut last_bind_group: Option<&Arc<wgpu::BindGroup>> = None;
+        for command in &self.commands {
+            let batches = match command {
+                Command::Draw(batch) => std::slice::from_ref(batch),
+                Command::Shadow(Shadow { batches, .. }) | Command::Group(Group { batches, .. }) => {
+                    stats.flushes += 1;
+                    &batches[..]
+                }
+                Command::Flush | Command::ClearDepth(_) => {
+                    stats.flushes += 1;
+                    &[][..]
+                }
+            };
+            for batch in batches {
+                stats.draw_calls += 1;
+                let bind_group = match &batch.kind {
+                    BatchKind::Textured(bind_group)
+                    | BatchKind::Premultiplied(bind_group)
+                    | BatchKind::Sdf(bind_group)
+                    | BatchKind::Multitexture(bind_group, _)
+                    | BatchKind::TextureArray(bind_group) => bind_group,
+                    _ => continue,
+                };
+                if !last_bind_group.is_some_and(|last| Arc::ptr_eq(last, bind_group)) {
+                    stats.texture_switches += 1;
+                }
+                last_bind_group = Some(bind_group);
+            }
+        }
+        stats
+    }
+
+    /// Returns the stencil reference value of the last recorded draw, if it is drawn with one.
+    ///
+    /// This helps checking nested clipping, where each level is compared with its own reference value.
+    pub fn current_stencil_ref(&self) -> Option<u8> {
+        let batch = self
+            .commands
+            .iter()
+            .rev()
+            .find_map(|command| match command {
+                Command::Draw(batch) => Some(batch),
+                Command::Shadow(_)
+                | Command::Group(_)
+                | Command::Flush
+                | Command::ClearDepth(_) => None,
+            })?;
+        match batch.kind {
+            BatchKind::Colored
+            | BatchKind::Lines
+            | BatchKind::Textured(_)
+            | BatchKind::Premultiplied(_)
+            | BatchKind::Sdf(_)
+            | BatchKind::Gradient(_)
+            | BatchKind::CircleArc(_)
+            | BatchKind::Multitexture(..)
+            | BatchKind::TextureArray(_) => stencil_reference(batch.draw_state.stencil),
+            BatchKind::PathFill(_) | BatchKind::StencilDecrement => None,
+            BatchKind::PathCover => Some(0),
+        }
+    }
+
+    /// Draws the triangles `xys` with `color`, without the closure of [`Graphics::tri_list`].
+    ///
+    /// Like [`Graphics::tri_list`], `xys` are in normalized device coordinates.
+    pub fn fill_triangles(&mut self, draw_state: &DrawState, color: &Color, xys: &[[f32; 2]]) {
+        self.tri_list(draw_state, color, |f| f(xys));
+    }
+
+    /// Draws the triangles `xys` with `texture` sampled at `uvs` and tinted by `color`,
+    /// without the closure of [`Graphics::tri_list_uv`].
+    ///
+    /// Like [`Graphics::tri_list_uv`], `xys` are in normalized device coordinates.
+    pub fn fill_triangles_uv(
+        &mut self,
+        draw_state: &DrawState,
+        color: &Color,
+        texture: &Texture,
+        xys: &[[f32; 2]],
+        uvs: &[[f32; 2]],
+    ) {
+        self.tri_list_uv(draw_state, color, texture, |f| f(xys, uvs));
+    }
+
+    /// Draws the `uv` region of `texture`, `[x, y, width, height]` in texture coordinates from 0 to 1,
+    /// onto `rect` transformed by `transform`, without expanding its two triangles by hand.
+    pub fn image(
+        &mut self,
+        texture: &Texture,
+        rect: [f64; 4],
+        uv: [f32; 4],
+        draw_state: &DrawState,
+        transform: Matrix2d,
+    ) {
+        let [x, y, w, h] = rect;
+        let [u, v, uw, vh] = uv;
+        let corner = |cx: f64, cy: f64| transform_position(transform, [cx, cy]);
+        let xys = quad(
+            corner(x, y),
+            corner(x + w, y),
+            corner(x + w, y + h),
+            corner(x, y + h),
+        );
+        let uvs = quad([u, v], [u + uw, v], [u + uw, v + vh], [u, v + vh]);
+        self.fill_triangles_uv(draw_state, &[1.0; 4], texture, &xys, &uvs);
+    }
+
+    /// Draws `texture` as a nine-patch onto `outer` transformed by `transform`, e.g. for scalable UI panels.
+    ///
+    /// `insets` are the `[left, top, right, bottom]` sizes of the borders, in texture pixels, which are also their sizes in `outer`.
+    /// Corners keep their size, edges stretch along their length and the center stretches both ways.
+    /// Insets are shrunk proportionally where they don't fit in `outer` or the texture.
+    pub fn nine_patch(
+        &mut self,
+        texture: &Texture,
+        outer: [f64; 4],
+        insets: [f64; 4],
+        draw_state: &DrawState,
+        transform: Matrix2d,
+    ) {
+        let (texture_width, texture_height) = texture.get_size();
+        let [x, y, w, h] = outer;
+        let [left, top, right, bottom] = insets.map(|inset| inset.max(0.0));
+        let fit = |start: f64, end: f64, size: f64| {
+            let scale = if start + end > size && start + end > 0.0 {
+                size / (start + end)
+            } else {
+                1.0
+            };
+            [start * scale, end * scale]
+        };
+        let [uv_left, uv_right] = fit(left, right, texture_width as f64);
+        let [uv_top, uv_bottom] = fit(top, bottom, texture_height as f64);
+        let [left, right] = fit(left, right, w.abs());
+        let [top, bottom] = fit(top, bottom, h.abs());
+
+        let xs = [x, x + left, x + w - right, x + w];
+        let ys = [y, y + top, y + h - bottom, y + h];
+        let us = [
+            0.0,
+            uv_left / texture_width as f64,
+            1.0 - uv_right / texture_width as f64,
+            1.0,
+        ];
+        let vs = [
+            0.0,
+            uv_top / texture_height as f64,
+            1.0 - uv_bottom / texture_height as f64,
+            1.0,
+        ];
+        let xy = |i: usize, j: usize| transform_position(transform, [xs[i], ys[j]]);
+        let uv = |i: usize, j: usize| [us[i] as f32, vs[j] as f32];
+
+        let mut xys = Vec::with_capacity(54);
+        let mut uvs = Vec::with_capacity(54);
+        for j in 0..3 {
+            for i in 0..3 {
+                xys.extend_from_slice(&quad(
+                    xy(i, j),
+                    xy(i + 1, j),
+                    xy(i + 1, j + 1),
+                    xy(i, j + 1),
+                ));
+                uvs.extend_from_slice(&quad(
+                    uv(i, j),
+                    uv(i + 1, j),
+                    uv(i + 1, j + 1),
+                    uv(i, j + 1),
+                ));
+            }
+        }
+        self.fill_triangles_uv(draw_state, &[1.0; 4], texture, &xys, &uvs);
+    }
+
+    /// Fills the area of the render target around `rect`, `[x, y, width, height]` in pixels, with `color`.
+    ///
+    /// With the [`letterbox_rect`] of a virtual resolution, this gives letterbox bars of a chosen color,
+    /// instead of leftovers of previous frames.
+    pub fn letterbox_bars(&mut self, rect: [f64; 4], color: Color) {
+        let [x, y, w, h] = rect;
+        let (width, height) = (self.width as f64, self.height as f64);
+        let (left, right) = (x.max(0.0).min(width), (x + w).max(0.0).min(width));
+        let (top, bottom) = (y.max(0.0).min(height), (y + h).max(0.0).min(height));
+        let bars = [
+            [0.0, 0.0, width, top],
+            [0.0, bottom, width, height],
+            [0.0, top, left, bottom],
+            [right, top, width, bottom],
+        ];
+        let to_ndc = |x: f64, y: f64| {
+            [
+                (2.0 * x / width - 1.0) as f32,
+                (1.0 - 2.0 * y / height) as f32,
+            ]
+        };
+        let positions = bars
+            .iter()
+            .filter(|&&[x0, y0, x1, y1]| x0 < x1 && y0 < y1)
+            .flat_map(|&[x0, y0, x1, y1]| {
+                [
+                    to_ndc(x0, y0),
+                    to_ndc(x1, y0),
+                    to_ndc(x1, y1),
+                    to_ndc(x0, y0),
+                    to_ndc(x1, y1),
+                    to_ndc(x0, y1),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let draw_state = DrawState {
+            scissor: None,
+            stencil: None,
+            blend: None,
+        };
+        self.fill_triangles(&draw_state, &color, &positions);
+    }
+
+    /// Ends the current render pass, so the following draws are encoded into a new one.
+    ///
+    /// Draws are never batched across a flush.
+    pub fn flush(&mut self) {
+        self.commands.push(Command::Flush);
+    }
+
+    /// Clears the depth to `value`, from 0 in front to 1 at the back, for the following draws.
+    ///
+    /// The current render pass is ended, and the next one starts by clearing the depth,
+    /// so draws before the clear stay drawn. The depth is also cleared to 1 at the start of each frame.
+    /// It only has an effect with [`Wgpu2dSettings::depth_test`].
+    pub fn clear_depth(&mut self, value: f32) {
+        self.commands
+            .push(Command::ClearDepth(value.clamp(0.0, 1.0)));
+    }
+
+    /// Draws everything drawn by `f` in its own render pass, without batching it with other draws.
+    ///
+    /// This costs extra render passes, but makes the draws of `f` easy to find in a GPU capture when debugging.
+    pub fn draw_immediate<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        self.flush();
+        f(self);
+        self.flush();
+    }
+
+    /// Draws a blurred shadow of everything drawn by `f`, behind it.
+    ///
+    /// The shadow is moved by `offset` and blurred by `blur`, both in pixels of the render target,
+    /// and filled with `color` where the shapes drawn by `f` cover the target.
+    pub fn with_shadow<F>(&mut self, offset: [f64; 2], blur: f64, color: Color, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        // The placeholder also keeps the first draw of `f` from being merged into a previous batch.
+        let start = self.commands.len();
+        self.commands.push(Command::Shadow(Shadow {
+            batches: vec![],
+            offset: [offset[0] as f32, offset[1] as f32],
+            blur: blur as f32,
+            color,
+        }));
+
+        f(self);
+
+        let batches = self.commands[start + 1..]
+            .iter()
+            .filter_map(|command| match command {
+                Command::Draw(batch) => Some(batch.clone()),
+                Command::Shadow(_)
+                | Command::Group(_)
+                | Command::Flush
+                | Command::ClearDepth(_) => None,
+            })
+            .collect::<Vec<_>>();
+        // The placeholder is gone if `f` cleared the color.
+        if let Some(Command::Shadow(shadow)) = self.commands.get_mut(start) {
+            if shadow.batches.is_empty() {
+                if batches.is_empty() {
+                    self.commands.remove(start);
+                } else {
+                    shadow.batches = batches;
+                }
+            }
+        }
+    }
+
+    /// Draws everything drawn by `f` with its opacity multiplied by `alpha`, as one unit.
+    ///
+    /// The draws of `f` are rendered into an offscreen layer, which is then drawn onto the render target,
+    /// so overlapping shapes don't show through each other when fading out.
+    /// The layer has a stencil buffer of its own, cleared to 0, and is drawn without clipping.
+    /// Shadows drawn by `f` are left out, and groups drawn by `f` are merged into this one.
+    pub fn with_group_opacity<F>(&mut self, alpha: f32, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        // The placeholder also keeps the first draw of `f` from being merged into a previous batch.
+        let start = self.commands.len();
+        self.commands.push(Command::Group(Group {
+            batches: vec![],
+            alpha,
+        }));
+
+        f(self);
+
+        // The placeholder is gone if `f` cleared the color.
+        if !matches!(
+            self.commands.get(start),
+            Some(Command::Group(group)) if group.batches.is_empty()
+        ) {
+            return;
+        }
+        let batches = self
+            .commands
+            .split_off(start + 1)
+            .into_iter()
+            .flat_map(|command| match command {
+                Command::Draw(batch) => vec![batch],
+                Command::Group(group) => group.batches,
+                Command::Shadow(_) | Command::Flush | Command::ClearDepth(_) => vec![],
+            })
+            .collect::<Vec<_>>();
+        if batches.is_empty() {
+            self.commands.remove(start);
+        } else if let Some(Command::Group(group)) = self.commands.get_mut(start) {
+            group.batches = batches;
+        }
+    }
+
+    /// Sets the rectangle `[x, y, width, height]`, in pixels of the render target,
+    /// which normalized device coordinates of the following draws are mapped to,
+    /// along with the range `depth_range` depths are mapped to.
+    ///
+    /// Unlike the scissor of a `DrawState`, which only clips what is drawn,
+    /// the viewport scales and moves the geometry, which is useful for split-screen or minimap layouts.
+    pub fn set_viewport_rect(&mut self, rect: [f32; 4], depth_range: Range<f32>) {
+        self.viewport = Some(ViewportRect {
+            rect,
+            depth_range: [depth_range.start, depth_range.end],
+        });
+    }
+
+    /// Sets the scale from the units of scissor rectangles of the following draw states to pixels of the render target,
+    /// 1 by default.
+    ///
+    /// On HiDPI displays, this lets scissor rectangles be computed in the logical units the context lays out in,
+    /// such as with [`Wgpu2d::draw_scaled`], which sets it. Scaled rectangles are rounded outwards to whole pixels.
+    pub fn set_scissor_scale(&mut self, scale: f64) {
+        self.scissor_scale = scale;
+    }
+
+    /// Sets the label of the debug group the following draw calls are wrapped in,
+    /// or draws them without one with `None`, which is the default.
+    ///
+    /// Graphics debuggers such as RenderDoc show draw calls under their label, inside the debug group
+    /// of their render pass: "Draws", "Shadow" or "Group". Draws with different labels aren't batched together.
+    pub fn set_debug_label(&mut self, label: Option<&'static str>) {
+        self.debug_label = label;
+    }
+
+    /// Sets the depth of the following draws, from 0 in front to 1 at the back,
+    /// or draws them at the depth of the viewport with `None`, which is the default.
+    ///
+    /// With [`Wgpu2dSettings::depth_test`], draws are layered by depth rather than by draw order,
+    /// a later draw only covering earlier ones at the same depth or behind. Without a depth, draws are at depth 0, in front.
+    /// It replaces the depth range set by [`WgpuGraphics::set_viewport_rect`].
+    pub fn set_depth(&mut self, depth: Option<f32>) {
+        self.depth = depth.map(|depth| depth.clamp(0.0, 1.0));
+    }
+
+    /// Returns `draw_state` clipped to `rect`, `[x, y, width, height]` in the coordinates `transform` maps to the render target,
+    /// such as the `transform` of a `Context`.
+    ///
+    /// The rectangle is transformed through the current viewport into pixels, and set as the scissor,
+    /// intersected with the scissor of `draw_state` if any, so clips can be nested.
+    /// Both scissors are in units of [`WgpuGraphics::set_scissor_scale`].
+    /// Scissors are axis-aligned, so a rotated or skewed rectangle is clipped to its bounding box instead.
+    pub fn clip_logical(
+        &self,
+        draw_state: &DrawState,
+        transform: Matrix2d,
+        rect: [f64; 4],
+    ) -> DrawState {
+        let [x, y, w, h] = rect;
+        let [vx, vy, vw, vh] = self.viewport.map_or(
+            [0.0, 0.0, self.width as f32, self.height as f32],
+            |viewport| viewport.rect,
+        );
+        let scale = self.scissor_scale;
+        let to_pixels = |px: f64, py: f64| {
+            let [[a, b, c], [d, e, f]] = transform;
+            let (nx, ny) = (a * px + b * py + c, d * px + e * py + f);
+            [
+                (vx as f64 + (nx + 1.0) / 2.0 * vw as f64) / scale,
+                (vy as f64 + (1.0 - ny) / 2.0 * vh as f64) / scale,
+            ]
+        };
+        let corners = [
+            to_pixels(x, y),
+            to_pixels(x + w, y),
+            to_pixels(x, y + h),
+            to_pixels(x + w, y + h),
+        ];
+        let (mut left, mut top) = (f64::INFINITY, f64::INFINITY);
+        let (mut right, mut bottom) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for [cx, cy] in corners.iter() {
+            left = left.min(*cx);
+            top = top.min(*cy);
+            right = right.max(*cx);
+            bottom = bottom.max(*cy);
+        }
+
+        let [sx, sy, sw, sh] = draw_state.scissor.unwrap_or([
+            0,
+            0,
+            (self.width as f64 / scale).ceil() as u32,
+            (self.height as f64 / scale).ceil() as u32,
+        ]);
+        let clamp_x = |x: f64| (x.floor().max(sx as f64) as u32).min(sx + sw);
+        let clamp_y = |y: f64| (y.floor().max(sy as f64) as u32).min(sy + sh);
+        let (left, top) = (clamp_x(left), clamp_y(top));
+        let (right, bottom) = (
+            clamp_x(right.ceil()).max(left),
+            clamp_y(bottom.ceil()).max(top),
+        );
+        DrawState {
+            scissor: Some([left, top, right - left, bottom - top]),
+            ..*draw_state
+        }
+    }
+
+    /// Resets the viewport of the following draws to the whole render target.
+    pub fn reset_viewport_rect(&mut self) {
+        self.viewport = None;
+    }
+
+    /// Sets the blend constant of the frame, which `Blend::Invert` subtracts the drawn colors from.
+    ///
+    /// It is white by default, giving plain inversion; another color gives a tinted inversion.
+    /// The constant applies to all the draws of the frame, as it is set once per render pass.
+    pub fn set_blend_constant(&mut self, color: Color) {
+        self.blend_constant = color;
+    }
+
+    /// Sets the alpha threshold below which texels of the following textured draws are discarded,
+    /// or disables discarding with `None`, which is the default.
+    ///
+    /// This gives hard cutout edges, e.g. for foliage sprites, which don't depend on the draw order like blending does.
+    /// The threshold is stored per vertex, so draws with different thresholds can still be batched together.
+    pub fn set_alpha_test(&mut self, threshold: Option<f32>) {
+        self.alpha_test = threshold;
+    }
+
+    /// Sets the filtering of textures in the following textured draws,
+    /// or uses the sampler of each texture with `None`, which is the default.
+    ///
+    /// This switches a texture between nearest and linear filtering, e.g. to inspect pixels when zoomed in,
+    /// without creating it again. The shared samplers clamp texture coordinates to the edges, whatever the wrap modes of the textures.
+    /// A bind group is created for each texture drawn with a filter, and reused until the filter changes.
+    pub fn set_filter(&mut self, filter: Option<Filter>) {
+        let unchanged = matches!(
+            (self.filter, filter),
+            (None, None)
+                | (Some(Filter::Nearest), Some(Filter::Nearest))
+                | (Some(Filter::Linear), Some(Filter::Linear))
+        );
+        if !unchanged {
+            self.filter = filter;
+            self.filtered_bind_groups.clear();
         }
     }
 
-    /// Performs 2D graphics operations and returns encoded commands.
+    /// Returns the bind group of `texture`, sampling it with the filter set by [`WgpuGraphics::set_filter`].
+    fn bind_group(&mut self, texture: &Texture) -> Arc<wgpu::BindGroup> {
+        let filter = match self.filter {
+            Some(filter) => filter,
+            None => return texture.bind_group.clone(),
+        };
+        let wgpu2d = self.wgpu2d;
+        self.filtered_bind_groups
+            .entry(Arc::as_ptr(&texture.bind_group) as usize)
+            .or_insert_with(|| {
+                (
+                    texture.bind_group.clone(),
+                    Arc::new(wgpu2d.filtered_bind_group(texture, filter)),
+                )
+            })
+            .1
+            .clone()
+    }
+
+    /// Draws the textured draws of `f` with their texels' alpha premultiplied into their color,
+    /// and blends them as premultiplied colors, ignoring the blend mode of their draw state.
+    ///
+    /// This is meant for text: glyph coverage is then blended like antialiasing,
+    /// which avoids the fringes of colored text on a differently colored background.
+    pub fn with_premultiplied_text<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let premultiplied = std::mem::replace(&mut self.premultiplied, true);
+        f(self);
+        self.premultiplied = premultiplied;
+    }
+
+    /// Draws line segments of `color` between each pair of `points`, 1 pixel wide whatever the transform.
     ///
-    /// To actually draw on a window surface, you must [`submit`](`wgpu::Queue::submit`) the returned [`CommandBuffer`](`wgpu::CommandBuffer`).
-    pub fn draw<F>(
+    /// Unlike lines drawn through the `graphics` crate, which are triangulated into quads,
+    /// they are rasterized as lines, keeping grid lines and debug overlays crisp at any zoom.
+    /// A last point without a pair is ignored.
+    pub fn line_list(
         &mut self,
-        device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        output_view: &wgpu::TextureView,
-        viewport: Viewport,
-        f: F,
-    ) -> wgpu::CommandBuffer
-    where
-        F: FnOnce(Context, &mut WgpuGraphics),
-    {
-        let mut g = WgpuGraphics::new(self, config);
-        let c = Context::new_viewport(viewport);
-        f(c, &mut g);
-        g.draw(device, output_view)
+        draw_state: &DrawState,
+        points: &[[f64; 2]],
+        transform: Matrix2d,
+        color: Color,
+    ) {
+        let positions = match self.transform_positions(&points[..points.len() / 2 * 2], transform) {
+            Some(positions) => positions,
+            None => return,
+        };
+        let pipeline_inputs = positions
+            .iter()
+            .map(|&position| ColoredPipelineInput { position, color });
+        self.push_colored_as(BatchKind::Lines, pipeline_inputs, draw_state);
     }
-}
 
-/// Graphics back-end.
-pub struct WgpuGraphics<'a> {
-    wgpu2d: &'a Wgpu2d<'a>,
-    width: u32,
-    height: u32,
-    color_format: wgpu::TextureFormat,
-    clear_color: Option<Color>,
-    clear_stencil: Option<u8>,
-    stencil_view: wgpu::TextureView,
-    render_bundles: Vec<(
-        &'a wgpu::RenderPipeline,
-        Option<[u32; 4]>,
-        Option<u8>,
-        wgpu::RenderBundle,
-    )>,
-}
+    /// Draws the triangles `xys`, transformed by `transform`, with `base` multiplied by `second`, both sampled at `uvs`.
+    ///
+    /// `blend_factor` goes from 0, drawing `base` only, to 1, fully modulating it by `second`,
+    /// as for a lightmap or a detail map.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_multitexture(
+        &mut self,
+        draw_state: &DrawState,
+        base: &Texture,
+        second: &Texture,
+        blend_factor: f32,
+        xys: &[[f64; 2]],
+        transform: Matrix2d,
+        uvs: &[[f32; 2]],
+    ) {
+        self.tri_list_uv2(
+            draw_state,
+            base,
+            second,
+            MultitextureMode::Multiply,
+            blend_factor,
+            xys,
+            transform,
+            uvs,
+            uvs,
+        );
+    }
 
-impl<'a> WgpuGraphics<'a> {
-    /// Creates a new `WgpuGraphics`.
-    pub fn new(wgpu2d: &'a Wgpu2d<'a>, config: &wgpu::SurfaceConfiguration) -> Self {
-        let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
-            depth_or_array_layers: 1,
+    /// Draws the triangles `xys`, transformed by `transform`, with `base` sampled at `uvs`,
+    /// combined with `second` sampled at `uvs2` by `mode`.
+    ///
+    /// `blend_factor` goes from 0, drawing `base` only, to 1, fully combining `second` with it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn tri_list_uv2(
+        &mut self,
+        draw_state: &DrawState,
+        base: &Texture,
+        second: &Texture,
+        mode: MultitextureMode,
+        blend_factor: f32,
+        xys: &[[f64; 2]],
+        transform: Matrix2d,
+        uvs: &[[f32; 2]],
+        uvs2: &[[f32; 2]],
+    ) {
+        let xys = match self.transform_positions(xys, transform) {
+            Some(xys) => xys,
+            None => return,
         };
-        let stencil = wgpu2d.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Stencil Texture"),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth24PlusStencil8,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[wgpu::TextureFormat::Depth24PlusStencil8],
-        });
-        let stencil_view = stencil.create_view(&wgpu::TextureViewDescriptor {
-            label: Some("Stencil Texture View"),
-            ..Default::default()
-        });
-        Self {
-            wgpu2d,
-            width: config.width,
-            height: config.height,
-            color_format: config.format,
-            clear_color: None,
-            clear_stencil: None,
-            stencil_view,
-            render_bundles: vec![],
-        }
+        let bind_group = self.wgpu2d.multitexture_pipelines.bind_group(
+            self.wgpu2d.device,
+            second,
+            mode,
+            blend_factor,
+        );
+        let start = self.multitexture_data.len() as u32;
+        self.multitexture_data.extend(
+            xys.iter()
+                .zip(uvs)
+                .zip(uvs2)
+                .map(|((&xy, &uv), &uv2)| MultitexturePipelineInput { xy, uv, uv2 }),
+        );
+        let end = self.multitexture_data.len() as u32;
+        self.push_batch(
+            BatchKind::Multitexture(base.bind_group.clone(), bind_group),
+            draw_state,
+            start..end,
+        );
     }
 
-    /// Performs 2D graphics operations and returns encoded commands.
+    /// Draws the triangles `xys`, transformed by `transform`, of `array` tinted with `color`,
+    /// each vertex sampling the layer of `layers` at its `uvs`.
     ///
-    /// To actually draw on a window surface, you must [`submit`](`wgpu::Queue::submit`) the returned [`CommandBuffer`](`wgpu::CommandBuffer`).
-    pub fn draw(
-        self,
-        device: &wgpu::Device,
-        output_view: &wgpu::TextureView,
-    ) -> wgpu::CommandBuffer {
-        let color_load = match self.clear_color {
-            Some(c) => wgpu::LoadOp::Clear(to_wgpu_color(c)),
-            None => wgpu::LoadOp::Load,
-        };
-        let stencil_load = match self.clear_stencil {
-            Some(s) => wgpu::LoadOp::Clear(s as u32),
-            None => wgpu::LoadOp::Load,
+    /// Triangles sampling different layers are drawn in one batch, e.g. a whole tile map, whereas each texture needs its own.
+    /// Vertices of a triangle should share a layer; layers out of range sample the nearest one.
+    /// [`WgpuGraphics::set_alpha_test`] doesn't apply to texture arrays.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_texture_array(
+        &mut self,
+        draw_state: &DrawState,
+        array: &TextureArray,
+        &color: &[f32; 4],
+        xys: &[[f64; 2]],
+        transform: Matrix2d,
+        uvs: &[[f32; 2]],
+        layers: &[u32],
+    ) {
+        let xys = match self.transform_positions(xys, transform) {
+            Some(xys) => xys,
+            None => return,
         };
+        let start = self.textured_data.len() as u32;
+        self.textured_data
+            .extend(xys.iter().zip(uvs).zip(layers).map(|((&xy, &uv), &layer)| {
+                TexturedPipelineInput {
+                    xy,
+                    uv,
+                    color,
+                    // The "texture array" shader reads the layer from the alpha threshold.
+                    alpha_test: layer as f32,
+                }
+            }));
+        let end = self.textured_data.len() as u32;
+        self.push_batch(
+            BatchKind::TextureArray(array.bind_group()),
+            draw_state,
+            start..end,
+        );
+    }
 
-        encode(device, |encoder| {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: output_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: color_load,
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.stencil_view,
-                    depth_ops: None,
-                    stencil_ops: Some(wgpu::Operations {
-                        load: stencil_load,
-                        store: true,
-                    }),
-                }),
-            });
-
-            render_pass.set_blend_constant(wgpu::Color::WHITE);
+    /// Draws `texture` onto the quad `corners`, transformed by `transform`,
+    /// mapping each corner to the matching texture coordinate in `uvs`.
+    ///
+    /// Corners are in the order top-left, top-right, bottom-right, bottom-left.
+    /// The quad is split into a grid of `subdivisions` by `subdivisions` cells, interpolated bilinearly,
+    /// which reduces the distortion of affine mapping across its two triangles.
+    /// True perspective correction would need a w coordinate per vertex, which this only approximates.
+    pub fn draw_warped_texture(
+        &mut self,
+        draw_state: &DrawState,
+        texture: &Texture,
+        corners: [[f64; 2]; 4],
+        transform: Matrix2d,
+        uvs: [[f32; 2]; 4],
+        subdivisions: u32,
+    ) {
+        let corners = corners.map(|corner| transform_position(transform, corner));
+        let subdivisions = subdivisions.max(1);
+        let alpha_test = self.alpha_test.unwrap_or(0.0);
+        let lerp =
+            |a: [f32; 2], b: [f32; 2], t: f32| [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t];
+        let bilerp = |points: [[f32; 2]; 4], s: f32, t: f32| {
+            lerp(
+                lerp(points[0], points[1], s),
+                lerp(points[3], points[2], s),
+                t,
+            )
+        };
+        let vertex = |i: u32, j: u32| {
+            let s = i as f32 / subdivisions as f32;
+            let t = j as f32 / subdivisions as f32;
+            TexturedPipelineInput {
+                xy: bilerp(corners, s, t),
+                uv: bilerp(uvs, s, t),
+                color: [1.0; 4],
+                alpha_test,
+            }
+        };
 
-            for &(pipeline, scissor, stencil_val, ref render_bundle) in &self.render_bundles {
-                let [x, y, width, height] = match scissor {
-                    Some(rect) => rect,
-                    None => [0, 0, self.width, self.height],
-                };
-                // NOTE: Calling `set_pipeline` is duplicated with the other call in RenderBundle.
-                // This is no a bug, because `set_stencil_reference` operation will be ignored
-                // unless we call it here, and that's because pipeline's stencil flag is checked on rendering.
-                // If wgpu implemented `set_stencil_reference` in `RenderBundleEncoder`, things could be simpler.
-                render_pass.set_pipeline(pipeline);
-
-                render_pass.set_scissor_rect(x, y, width, height);
-                if let Some(stencil_val) = stencil_val {
-                    render_pass.set_stencil_reference(stencil_val as u32);
-                }
-                render_pass.execute_bundles(std::iter::once(render_bundle));
+        let mut inputs = Vec::with_capacity(6 * (subdivisions * subdivisions) as usize);
+        for j in 0..subdivisions {
+            for i in 0..subdivisions {
+                inputs.extend_from_slice(&quad(
+                    vertex(i, j),
+                    vertex(i + 1, j),
+                    vertex(i + 1, j + 1),
+                    vertex(i, j + 1),
+                ));
             }
-        })
+        }
+        self.push_textured(inputs, texture, draw_state);
     }
 
-    fn bundle<'b, F>(&self, device: &'b wgpu::Device, f: F) -> wgpu::RenderBundle
-    where
-        F: FnOnce(&mut dyn wgpu::util::RenderEncoder<'b>),
-    {
-        let mut render_bundle_encoder =
-            device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
-                label: Some("Render Bundle Encoder"),
-                color_formats: &[Some(self.color_format)],
-                depth_stencil: Some(wgpu::RenderBundleDepthStencil {
-                    format: wgpu::TextureFormat::Depth24PlusStencil8,
-                    depth_read_only: true,
-                    stencil_read_only: false,
-                }),
-                sample_count: 1,
-                multiview: None,
-            });
+    /// Fills the polygon `path`, transformed by `transform`, with `color`, following `fill_rule` where it intersects itself.
+    ///
+    /// The stencil buffer is used to count windings, so the stencil of `draw_state` is ignored,
+    /// and the stencil is reset to 0 inside the bounding box of `path`.
+    pub fn fill_path(
+        &mut self,
+        draw_state: &DrawState,
+        path: &[[f64; 2]],
+        transform: Matrix2d,
+        fill_rule: FillRule,
+        &color: &Color,
+    ) {
+        if path.len() < 3 {
+            return;
+        }
+        let path = match self.transform_positions(path, transform) {
+            Some(path) => path,
+            None => return,
+        };
+        let first = path[0];
+        let mut fan = Vec::with_capacity(3 * (path.len() - 2));
+        for edge in path[1..].windows(2) {
+            fan.extend_from_slice(&[first, edge[0], edge[1]]);
+        }
+        self.push_colored_as(
+            BatchKind::PathFill(fill_rule),
+            fan.into_iter()
+                .map(|position| ColoredPipelineInput { position, color }),
+            draw_state,
+        );
 
-        f(&mut render_bundle_encoder);
+        let mut min = first;
+        let mut max = first;
+        for &[x, y] in &path {
+            min = [min[0].min(x), min[1].min(y)];
+            max = [max[0].max(x), max[1].max(y)];
+        }
+        let cover = quad(min, [max[0], min[1]], max, [min[0], max[1]]);
+        self.push_colored_as(
+            BatchKind::PathCover,
+            cover
+                .iter()
+                .map(|&position| ColoredPipelineInput { position, color }),
+            draw_state,
+        );
+    }
 
-        render_bundle_encoder.finish(&wgpu::RenderBundleDescriptor {
-            label: Some("Render Bundle"),
-        })
+    /// Decrements the stencil buffer where the triangles `xys`, transformed by `transform`, are,
+    /// clamped to 0, without drawing colors.
+    ///
+    /// This pops a level of nested clips drawn with `Stencil::Increment`, which the `Stencil` of [`DrawState`] can't express,
+    /// for applications managing their own nesting. The stencil of `draw_state` is ignored, but its scissor applies.
+    pub fn decrement_stencil(
+        &mut self,
+        draw_state: &DrawState,
+        xys: &[[f64; 2]],
+        transform: Matrix2d,
+    ) {
+        let xys = match self.transform_positions(xys, transform) {
+            Some(xys) => xys,
+            None => return,
+        };
+        self.push_colored_as(
+            BatchKind::StencilDecrement,
+            xys.iter().map(|&position| ColoredPipelineInput {
+                position,
+                color: [0.0; 4],
+            }),
+            draw_state,
+        );
     }
 
-    fn bundle_colored(&mut self, colored_inputs: &[ColoredPipelineInput], draw_state: &DrawState) {
-        let vertex_buffer =
-            self.wgpu2d
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Vertex Buffer"),
-                    contents: bytemuck::cast_slice(colored_inputs),
-                    usage: wgpu::BufferUsages::VERTEX,
+    /// Returns `true` unless [`Wgpu2dSettings::debug_validate`] is set and `positions` has a non-finite coordinate,
+    /// in which case the first such vertex is recorded as invalid.
+    fn validate_positions(&mut self, positions: &[[f32; 2]]) -> bool {
+        if !self.wgpu2d.settings.debug_validate {
+            return true;
+        }
+        match positions
+            .iter()
+            .position(|&[x, y]| !x.is_finite() || !y.is_finite())
+        {
+            Some(index) => {
+                self.invalid_vertices.push(InvalidVertex {
+                    index,
+                    position: positions[index],
                 });
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Returns `positions` transformed by `transform`, or `None` if they fail [`WgpuGraphics::validate_positions`].
+    fn transform_positions(
+        &mut self,
+        positions: &[[f64; 2]],
+        transform: Matrix2d,
+    ) -> Option<Vec<[f32; 2]>> {
+        let positions: Vec<_> = positions
+            .iter()
+            .map(|&position| transform_position(transform, position))
+            .collect();
+        if self.validate_positions(&positions) {
+            Some(positions)
+        } else {
+            None
+        }
+    }
+
+    /// Appends a batch, merging it into the last one if they can be drawn in one call.
+    fn push_batch(&mut self, kind: BatchKind, draw_state: &DrawState, vertices: Range<u32>) {
+        self.push_batch_indexed(kind, draw_state, vertices, None);
+    }
 
-        let (pipeline, stencil_val) = self
-            .wgpu2d
-            .colored_render_pipelines
-            .stencil_blend(draw_state.stencil, draw_state.blend);
+    /// Appends a batch drawing `vertices`, or `indices` of the index buffer if any,
+    /// merging it into the last batch when they can be drawn in one call.
+    fn push_batch_indexed(
+        &mut self,
+        kind: BatchKind,
+        draw_state: &DrawState,
+        vertices: Range<u32>,
+        indices: Option<Range<u32>>,
+    ) {
+        if vertices.is_empty() || indices.as_ref().is_some_and(|indices| indices.is_empty()) {
+            return;
+        }
+        let draw_state = &self.scale_scissor(draw_state);
+        let viewport = match self.depth {
+            Some(depth) => Some(ViewportRect {
+                rect: self.viewport.map_or(
+                    [0.0, 0.0, self.width as f32, self.height as f32],
+                    |viewport| viewport.rect,
+                ),
+                depth_range: [depth, depth],
+            }),
+            None => self.viewport,
+        };
+        if let Some(Command::Draw(last)) = self.commands.last_mut() {
+            let contiguous = match (&last.indices, &indices) {
+                (None, None) => last.vertices.end == vertices.start,
+                (Some(last_indices), Some(indices)) => last_indices.end == indices.start,
+                _ => false,
+            };
+            if last.draw_state == *draw_state
+                && last.viewport == viewport
+                && last.label == self.debug_label
+                && last.kind.is_compatible(&kind)
+                && contiguous
+            {
+                last.vertices.end = vertices.end;
+                if let (Some(last_indices), Some(indices)) = (&mut last.indices, indices) {
+                    last_indices.end = indices.end;
+                }
+                return;
+            }
+        }
+        self.commands.push(Command::Draw(Batch {
+            kind,
+            draw_state: *draw_state,
+            viewport,
+            vertices,
+            indices,
+            label: self.debug_label,
+        }));
+    }
 
-        let render_bundle = self.bundle(self.wgpu2d.device, |render_encoder| {
-            render_encoder.set_pipeline(pipeline);
-            render_encoder.set_vertex_buffer(0, vertex_buffer.slice(..));
-            render_encoder.draw(0..colored_inputs.len() as u32, 0..1);
+    /// Returns `draw_state` with its scissor rectangle scaled to pixels by the scale of [`WgpuGraphics::set_scissor_scale`],
+    /// rounded outwards.
+    fn scale_scissor(&self, draw_state: &DrawState) -> DrawState {
+        let scissor = draw_state.scissor.map(|[x, y, width, height]| {
+            let scale = self.scissor_scale;
+            let (left, top) = ((x as f64 * scale).floor(), (y as f64 * scale).floor());
+            let right = ((x as f64 + width as f64) * scale).ceil();
+            let bottom = ((y as f64 + height as f64) * scale).ceil();
+            [
+                left as u32,
+                top as u32,
+                (right - left) as u32,
+                (bottom - top) as u32,
+            ]
         });
+        DrawState {
+            scissor,
+            ..*draw_state
+        }
+    }
 
-        self.render_bundles
-            .push((pipeline, draw_state.scissor, stencil_val, render_bundle));
+    /// Appends `indices` into vertices starting at `base`, offset to point into the whole vertex buffer.
+    ///
+    /// Returns the range of the appended indices.
+    fn push_indices(&mut self, indices: &[u32], base: u32) -> Range<u32> {
+        let start = self.index_data.len() as u32;
+        self.index_data
+            .extend(indices.iter().map(|&index| base + index));
+        start..self.index_data.len() as u32
     }
 
-    fn bundle_textured(
+    /// Draws triangles of `color`, whose corners are given by `indices` into `positions`,
+    /// transformed by `transform`.
+    ///
+    /// Positions are shared between triangles instead of repeated, e.g. 4 per quad instead of 6.
+    /// Nothing is drawn if an index is out of range of `positions`, or `indices` doesn't hold whole triangles.
+    pub fn push_indexed_colored(
         &mut self,
-        textured_inputs: &[TexturedPipelineInput],
-        texture: &Texture,
         draw_state: &DrawState,
+        &color: &[f32; 4],
+        positions: &[[f64; 2]],
+        transform: Matrix2d,
+        indices: &[u32],
     ) {
-        let vertex_buffer =
-            self.wgpu2d
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Vertex Buffer"),
-                    contents: bytemuck::cast_slice(textured_inputs),
-                    usage: wgpu::BufferUsages::VERTEX,
-                });
+        if !valid_indices(indices, positions.len()) {
+            return;
+        }
+        let positions = match self.transform_positions(positions, transform) {
+            Some(positions) => positions,
+            None => return,
+        };
+        let start = self.colored_data.len() as u32;
+        self.colored_data.extend(
+            positions
+                .iter()
+                .map(|&position| ColoredPipelineInput { position, color }),
+        );
+        let end = self.colored_data.len() as u32;
+        let indices = self.push_indices(indices, start);
+        self.push_batch_indexed(BatchKind::Colored, draw_state, start..end, Some(indices));
+    }
+
+    /// Draws triangles of `texture` tinted with `color`, whose corners are given by `indices`
+    /// into `positions`, transformed by `transform`, and texture coordinates `uvs`.
+    ///
+    /// Positions are shared between triangles instead of repeated, e.g. 4 per quad instead of 6.
+    /// Nothing is drawn if an index is out of range of `positions` or `uvs`,
+    /// or `indices` doesn't hold whole triangles.
+    #[allow(clippy::too_many_arguments)]
+    pub fn push_indexed_textured(
+        &mut self,
+        draw_state: &DrawState,
+        &color: &[f32; 4],
+        texture: &Texture,
+        positions: &[[f64; 2]],
+        transform: Matrix2d,
+        uvs: &[[f32; 2]],
+        indices: &[u32],
+    ) {
+        if !valid_indices(indices, positions.len().min(uvs.len())) {
+            return;
+        }
+        let positions = match self.transform_positions(positions, transform) {
+            Some(positions) => positions,
+            None => return,
+        };
+        let alpha_test = self.alpha_test.unwrap_or(0.0);
+        let start = self.textured_data.len() as u32;
+        self.textured_data
+            .extend(
+                positions
+                    .iter()
+                    .zip(uvs.iter())
+                    .map(|(&xy, &uv)| TexturedPipelineInput {
+                        xy,
+                        uv,
+                        color,
+                        alpha_test,
+                    }),
+            );
+        let end = self.textured_data.len() as u32;
+        let indices = self.push_indices(indices, start);
+        let bind_group = self.bind_group(texture);
+        let kind = if self.premultiplied {
+            BatchKind::Premultiplied(bind_group)
+        } else {
+            BatchKind::Textured(bind_group)
+        };
+        self.push_batch_indexed(kind, draw_state, start..end, Some(indices));
+    }
 
-        let (pipeline, stencil_val) = self
-            .wgpu2d
-            .textured_render_pipelines
-            .stencil_blend(draw_state.stencil, draw_state.blend);
+    fn push_colored<I>(&mut self, colored_inputs: I, draw_state: &DrawState)
+    where
+        I: IntoIterator<Item = ColoredPipelineInput>,
+    {
+        self.push_colored_as(BatchKind::Colored, colored_inputs, draw_state);
+    }
 
-        let render_bundle = self.bundle(self.wgpu2d.device, |render_encoder| {
-            render_encoder.set_pipeline(pipeline);
-            render_encoder.set_bind_group(0, &texture.bind_group, &[]);
-            render_encoder.set_vertex_buffer(0, vertex_buffer.slice(..));
-            render_encoder.draw(0..textured_inputs.len() as u32, 0..1);
-        });
+    /// Appends vertices for the "colored" pipeline, drawn as `kind`.
+    fn push_colored_as<I>(&mut self, kind: BatchKind, colored_inputs: I, draw_state: &DrawState)
+    where
+        I: IntoIterator<Item = ColoredPipelineInput>,
+    {
+        let start = self.colored_data.len() as u32;
+        self.colored_data.extend(colored_inputs);
+        let end = self.colored_data.len() as u32;
+        self.push_batch(kind, draw_state, start..end);
+    }
+
+    /// Appends the quad `corners`, top-left, top-right, bottom-right and bottom-left, drawn as `kind`.
+    fn push_quad(
+        &mut self,
+        kind: BatchKind,
+        draw_state: &DrawState,
+        [top_left, top_right, bottom_right, bottom_left]: [TexturedPipelineInput; 4],
+    ) {
+        let start = self.textured_data.len() as u32;
+        self.textured_data
+            .extend_from_slice(&quad(top_left, top_right, bottom_right, bottom_left));
+        let end = self.textured_data.len() as u32;
+        self.push_batch(kind, draw_state, start..end);
+    }
 
-        self.render_bundles
-            .push((pipeline, draw_state.scissor, stencil_val, render_bundle));
+    fn push_textured<I>(&mut self, textured_inputs: I, texture: &Texture, draw_state: &DrawState)
+    where
+        I: IntoIterator<Item = TexturedPipelineInput>,
+    {
+        let start = self.textured_data.len() as u32;
+        self.textured_data.extend(textured_inputs);
+        let end = self.textured_data.len() as u32;
+        let bind_group = self.bind_group(texture);
+        let kind = if self.premultiplied {
+            BatchKind::Premultiplied(bind_group)
+        } else {
+            BatchKind::Textured(bind_group)
+        };
+        self.push_batch(kind, draw_state, start..end);
     }
 }
 
@@ -889,14 +4748,21 @@ impl<'a> Graphics for WgpuGraphics<'a> {
 
     fn clear_color(&mut self, color: Color) {
         self.clear_color = Some(color);
-        self.render_bundles
-            .retain(|&(_, _, stencil_val, _)| stencil_val.is_some());
+        self.commands.retain(|command| match command {
+            Command::Draw(batch) => batch.has_stencil_reference(),
+            Command::Shadow(_) | Command::Group(_) => false,
+            Command::Flush | Command::ClearDepth(_) => true,
+        });
     }
 
     fn clear_stencil(&mut self, value: u8) {
         self.clear_stencil = Some(value);
-        self.render_bundles
-            .retain(|&(_, _, stencil_val, _)| stencil_val.is_none());
+        self.commands.retain(|command| match command {
+            Command::Draw(batch) => !batch.has_stencil_reference(),
+            Command::Shadow(_) | Command::Group(_) | Command::Flush | Command::ClearDepth(_) => {
+                true
+            }
+        });
     }
 
     fn tri_list<F>(&mut self, draw_state: &DrawState, &color: &[f32; 4], mut f: F)
@@ -904,12 +4770,14 @@ impl<'a> Graphics for WgpuGraphics<'a> {
         F: FnMut(&mut dyn FnMut(&[[f32; 2]])),
     {
         f(&mut |positions| {
+            if !self.validate_positions(positions) {
+                return;
+            }
             let pipeline_inputs = positions
                 .iter()
-                .map(|&position| ColoredPipelineInput { position, color })
-                .collect::<Vec<_>>();
+                .map(|&position| ColoredPipelineInput { position, color });
 
-            self.bundle_colored(&pipeline_inputs, draw_state);
+            self.push_colored(pipeline_inputs, draw_state);
         });
     }
 
@@ -918,13 +4786,15 @@ impl<'a> Graphics for WgpuGraphics<'a> {
         F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]])),
     {
         f(&mut |positions, colors| {
+            if !self.validate_positions(positions) {
+                return;
+            }
             let pipeline_inputs = positions
                 .iter()
                 .zip(colors.iter())
-                .map(|(&position, &color)| ColoredPipelineInput { position, color })
-                .collect::<Vec<_>>();
+                .map(|(&position, &color)| ColoredPipelineInput { position, color });
 
-            self.bundle_colored(&pipeline_inputs, draw_state);
+            self.push_colored(pipeline_inputs, draw_state);
         });
     }
 
@@ -937,14 +4807,22 @@ impl<'a> Graphics for WgpuGraphics<'a> {
     ) where
         F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]])),
     {
+        let alpha_test = self.alpha_test.unwrap_or(0.0);
         f(&mut |xys, uvs| {
-            let pipeline_inputs = xys
-                .iter()
-                .zip(uvs.iter())
-                .map(|(&xy, &uv)| TexturedPipelineInput { xy, uv, color })
-                .collect::<Vec<_>>();
+            if !self.validate_positions(xys) {
+                return;
+            }
+            let pipeline_inputs =
+                xys.iter()
+                    .zip(uvs.iter())
+                    .map(|(&xy, &uv)| TexturedPipelineInput {
+                        xy,
+                        uv,
+                        color,
+                        alpha_test,
+                    });
 
-            self.bundle_textured(&pipeline_inputs, texture, draw_state);
+            self.push_textured(pipeline_inputs, texture, draw_state);
         })
     }
 
@@ -952,15 +4830,23 @@ impl<'a> Graphics for WgpuGraphics<'a> {
     where
         F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]])),
     {
+        let alpha_test = self.alpha_test.unwrap_or(0.0);
         f(&mut |xys, uvs, colors| {
-            let pipeline_inputs = xys
-                .iter()
-                .zip(uvs.iter())
-                .zip(colors.iter())
-                .map(|((&xy, &uv), &color)| TexturedPipelineInput { xy, uv, color })
-                .collect::<Vec<_>>();
+            if !self.validate_positions(xys) {
+                return;
+            }
+            let pipeline_inputs =
+                xys.iter()
+                    .zip(uvs.iter())
+                    .zip(colors.iter())
+                    .map(|((&xy, &uv), &color)| TexturedPipelineInput {
+                        xy,
+                        uv,
+                        color,
+                        alpha_test,
+                    });
 
-            self.bundle_textured(&pipeline_inputs, texture, draw_state);
+            self.push_textured(pipeline_inputs, texture, draw_state);
         })
     }
 }
@@ -978,6 +4864,233 @@ where
     encoder.finish()
 }
 
+/// Returns the two triangles of the quad with these corners.
+fn quad<T: Copy>(top_left: T, top_right: T, bottom_right: T, bottom_left: T) -> [T; 6] {
+    [
+        top_left,
+        top_right,
+        bottom_right,
+        top_left,
+        bottom_right,
+        bottom_left,
+    ]
+}
+
+/// Returns the positions and texture coordinates of a quad showing a whole texture
+/// over `[left, top, right, bottom]`, in normalized device coordinates.
+fn texture_quad<T: Copy>([left, top, right, bottom]: [T; 4]) -> ([[T; 2]; 6], [[f32; 2]; 6]) {
+    (
+        quad([left, top], [right, top], [right, bottom], [left, bottom]),
+        quad([0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]),
+    )
+}
+
+/// Returns `[x, y]` transformed by `transform`, as a vertex position.
+fn transform_position([[a, b, c], [d, e, f]]: Matrix2d, [x, y]: [f64; 2]) -> [f32; 2] {
+    [(a * x + b * y + c) as f32, (d * x + e * y + f) as f32]
+}
+
+/// Reorders `batches` so those drawn with the same pipeline, bindings, draw state and viewport are consecutive.
+///
+/// Batches are never moved across one writing the stencil buffer.
+fn group_batches(batches: Vec<&Batch>) -> Vec<&Batch> {
+    let mut grouped = Vec::with_capacity(batches.len());
+    let mut groups: Vec<Vec<&Batch>> = vec![];
+    for batch in batches {
+        if batch.writes_stencil() {
+            grouped.extend(groups.drain(..).flatten());
+            grouped.push(batch);
+            continue;
+        }
+        let group = groups.iter_mut().find(|group| {
+            let first = group[0];
+            first.draw_state == batch.draw_state
+                && first.viewport == batch.viewport
+                && first.label == batch.label
+                && first.kind.is_compatible(&batch.kind)
+        });
+        match group {
+            Some(group) => group.push(batch),
+            None => groups.push(vec![batch]),
+        }
+    }
+    grouped.extend(groups.into_iter().flatten());
+    grouped
+}
+
+/// Returns `scissor`, `[x, y, width, height]` in pixels, clamped to a render target of `size`.
+fn clamp_scissor(
+    [x, y, width, height]: [u32; 4],
+    [target_width, target_height]: [u32; 2],
+) -> [u32; 4] {
+    let (left, top) = (x.min(target_width), y.min(target_height));
+    let right = x.saturating_add(width).min(target_width);
+    let bottom = y.saturating_add(height).min(target_height);
+    [left, top, right - left, bottom - top]
+}
+
+/// Returns `true` if `indices` hold whole triangles, of vertices among `vertex_count`.
+fn valid_indices(indices: &[u32], vertex_count: usize) -> bool {
+    indices.len().is_multiple_of(3) && indices.iter().all(|&index| (index as usize) < vertex_count)
+}
+
+/// Returns the stencil reference value used by `stencil`, if any.
+fn stencil_reference(stencil: Option<Stencil>) -> Option<u8> {
+    match stencil {
+        Some(Stencil::Clip(val)) | Some(Stencil::Inside(val)) | Some(Stencil::Outside(val)) => {
+            Some(val)
+        }
+        None | Some(Stencil::Increment) => None,
+    }
+}
+
+/// Returns `blend` adapted to source colors with their alpha premultiplied.
+///
+/// The alpha factor of the source color is dropped, as it is already in the color,
+/// and multiplication keeps the destination where the source is transparent.
+fn premultiplied_blend(blend: wgpu::BlendState) -> wgpu::BlendState {
+    use wgpu::BlendFactor;
+
+    let mut color = blend.color;
+    match (color.src_factor, color.dst_factor) {
+        (BlendFactor::SrcAlpha, _) => color.src_factor = BlendFactor::One,
+        (BlendFactor::Dst, BlendFactor::Zero) => color.dst_factor = BlendFactor::OneMinusSrcAlpha,
+        _ => {}
+    }
+    wgpu::BlendState { color, ..blend }
+}
+
+/// Returns the `[x, y, width, height]` rectangle, in pixels of `output_size`,
+/// which content rendered at `internal_size` is scaled into, centered and keeping its aspect ratio.
+///
+/// With `integer_scale`, the scale is the largest integer factor fitting in `output_size`, and at least 1,
+/// as [`Wgpu2d::draw_pixel_perfect`] does. The rest of the output can be filled with [`WgpuGraphics::letterbox_bars`].
+pub fn letterbox_rect(
+    [width, height]: [u32; 2],
+    [output_width, output_height]: [u32; 2],
+    integer_scale: bool,
+) -> [f64; 4] {
+    let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+    let (output_width, output_height) = (output_width as f64, output_height as f64);
+    let scale = (output_width / width).min(output_height / height);
+    let scale = if integer_scale {
+        scale.floor().max(1.0)
+    } else {
+        scale
+    };
+    let (w, h) = (width * scale, height * scale);
+    [(output_width - w) / 2.0, (output_height - h) / 2.0, w, h]
+}
+
+/// Returns a configuration of `surface` of `[width, height]` pixels, with a format, present mode and alpha mode it supports with `adapter`.
+///
+/// The format is the first sRGB one the surface supports, or its preferred one if none is sRGB.
+/// The present mode is `Fifo`, which waits for vertical sync and is always supported,
+/// and the alpha mode is the first the surface supports.
+pub fn default_surface_config(
+    surface: &wgpu::Surface,
+    adapter: &wgpu::Adapter,
+    [width, height]: [u32; 2],
+) -> wgpu::SurfaceConfiguration {
+    let capabilities = surface.get_capabilities(adapter);
+    let format = capabilities
+        .formats
+        .iter()
+        .copied()
+        .find(|format| format.is_srgb())
+        .or_else(|| capabilities.formats.first().copied())
+        .unwrap_or(wgpu::TextureFormat::Bgra8UnormSrgb);
+    let alpha_mode = capabilities
+        .alpha_modes
+        .first()
+        .copied()
+        .unwrap_or(wgpu::CompositeAlphaMode::Auto);
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width,
+        height,
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode,
+        view_formats: vec![],
+    }
+}
+
+/// Polls `future` once, returning its output if it is ready, as it is right away for error scopes of native devices.
+fn poll_now<F: Future>(future: F) -> Option<F::Output> {
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut future = Box::pin(future);
+    match future.as_mut().poll(&mut TaskContext::from_waker(&waker)) {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None,
+    }
+}
+
+/// Returns `true` if `device` can render to textures of `format`, and to the stencil buffer, with `count` samples.
+fn supports_sample_count(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    stencil_format: wgpu::TextureFormat,
+    count: u32,
+) -> bool {
+    [format, stencil_format].iter().all(|format| {
+        format
+            .guaranteed_format_features(device.features())
+            .flags
+            .sample_count_supported(count)
+    })
+}
+
+/// Returns the first stencil format `device` can render to, of `preferred`, `Depth24PlusStencil8` and `Depth32FloatStencil8`.
+///
+/// Falls back to `Depth24PlusStencil8` if none is available.
+fn select_stencil_format(
+    device: &wgpu::Device,
+    preferred: Option<wgpu::TextureFormat>,
+) -> wgpu::TextureFormat {
+    preferred
+        .filter(|format| format.has_stencil_aspect())
+        .into_iter()
+        .chain([
+            wgpu::TextureFormat::Depth24PlusStencil8,
+            wgpu::TextureFormat::Depth32FloatStencil8,
+        ])
+        .find(|&format| is_stencil_renderable(device, format))
+        .unwrap_or(wgpu::TextureFormat::Depth24PlusStencil8)
+}
+
+/// Returns `true` if `device` can render to stencil textures of `format`.
+fn is_stencil_renderable(device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+    format
+        .required_features()
+        .difference(device.features())
+        .is_empty()
+        && format
+            .guaranteed_format_features(device.features())
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+}
+
+/// Returns `true` if `device` can render to and blend into textures of `format`.
+fn is_renderable(device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+    let features = format.guaranteed_format_features(device.features());
+    features
+        .allowed_usages
+        .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+        && features
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::BLENDABLE)
+}
+
+/// Converts an RGBA `color` to a `wgpu::Color`.
+///
+/// `wgpu::Color` is always RGBA; wgpu reorders it for BGRA targets.
 fn to_wgpu_color(color: Color) -> wgpu::Color {
     wgpu::Color {
         r: color[0] as f64,
@@ -986,3 +5099,50 @@ fn to_wgpu_color(color: Color) -> wgpu::Color {
         a: color[3] as f64,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Largest error of a color channel quantized to 8 bits, with some slack for halfway values.
+    const UNORM8_TOLERANCE: f32 = 0.501 / 255.0;
+    /// Largest error of a texture coordinate from 0 to 1 converted to `f16`, with its 11 significant bits.
+    const F16_TOLERANCE: f32 = 1.0 / 4096.0;
+
+    #[test]
+    fn compact_colored_input_keeps_color() {
+        let input = ColoredPipelineInput {
+            position: [12.5, -3.25],
+            color: [0.0, 0.3, 0.7, 1.0],
+        };
+        let compact = CompactColoredPipelineInput::from(&input);
+        assert_eq!(compact.position, input.position);
+        for (&byte, &channel) in compact.color.iter().zip(&input.color) {
+            assert!((byte as f32 / 255.0 - channel).abs() <= UNORM8_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn compact_textured_input_keeps_uv_and_color() {
+        let input = TexturedPipelineInput {
+            xy: [0.5, 0.75],
+            uv: [0.1234, 0.9876],
+            color: [0.2, 0.4, 0.6, 0.8],
+            alpha_test: 0.5,
+        };
+        let compact = CompactTexturedPipelineInput::from(&input);
+        assert_eq!(compact.xy, input.xy);
+        assert_eq!(compact.alpha_test, input.alpha_test);
+        for (&bits, &coordinate) in compact.uv.iter().zip(&input.uv) {
+            assert!((half::f16::from_bits(bits).to_f32() - coordinate).abs() <= F16_TOLERANCE);
+        }
+        for (&byte, &channel) in compact.color.iter().zip(&input.color) {
+            assert!((byte as f32 / 255.0 - channel).abs() <= UNORM8_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn compact_colors_are_clamped() {
+        assert_eq!(to_unorm8x4([-0.5, 0.0, 1.0, 1.5]), [0, 0, 255, 255]);
+    }
+}
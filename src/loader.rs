@@ -0,0 +1,173 @@
+//! Loading of several textures in one upload.
+
+use crate::{encode, Texture, TextureContext, TextureError};
+use image::RgbaImage;
+use std::borrow::Cow;
+use texture::TextureSettings;
+use wgpu::util::DeviceExt;
+
+/// An image added to a [`TextureLoader`], waiting to be uploaded.
+struct PendingTexture {
+    offset: wgpu::BufferAddress,
+    size: [u32; 2],
+    settings: TextureSettings,
+}
+
+/// Creates several textures at once, copying them all from a single staging buffer.
+///
+/// This avoids an upload per texture when loading many assets at startup.
+/// The staging data keeps its allocation between loads, so a loader can be reused for each batch of assets.
+#[derive(Default)]
+pub struct TextureLoader {
+    data: Vec<u8>,
+    pending: Vec<PendingTexture>,
+}
+
+impl TextureLoader {
+    /// Creates a new empty `TextureLoader`.
+    pub fn new() -> Self {
+        TextureLoader::default()
+    }
+
+    /// Adds `img`, to be created with `settings` by the next [`TextureLoader::load`].
+    pub fn add(&mut self, img: &RgbaImage, settings: &TextureSettings) -> &mut Self {
+        let (width, height) = img.dimensions();
+        let row_size = 4 * width as usize;
+        let padded_row_size = padded_row_size(width);
+        let offset = self.data.len();
+
+        self.data
+            .resize(offset + padded_row_size * height as usize, 0);
+        if row_size > 0 {
+            for (row, padded_row) in img
+                .as_raw()
+                .chunks(row_size)
+                .zip(self.data[offset..].chunks_mut(padded_row_size))
+            {
+                padded_row[..row_size].copy_from_slice(row);
+            }
+        }
+        self.pending.push(PendingTexture {
+            offset: offset as wgpu::BufferAddress,
+            size: [width, height],
+            settings: *settings,
+        });
+        self
+    }
+
+    /// Creates the added textures in one submission, returning them in the order they were added.
+    ///
+    /// The loader is emptied, even if an error is returned, in which case no texture is created.
+    pub fn load<'a>(
+        &mut self,
+        context: &mut TextureContext<'a>,
+    ) -> Result<Vec<Texture>, TextureError> {
+        let pending = std::mem::take(&mut self.pending);
+        let result = self.upload(context, pending);
+        self.data.clear();
+        result
+    }
+
+    fn upload<'a>(
+        &self,
+        context: &mut TextureContext<'a>,
+        pending: Vec<PendingTexture>,
+    ) -> Result<Vec<Texture>, TextureError> {
+        for texture in &pending {
+            let [width, height] = texture.size;
+            context.check_upload(
+                texture.size,
+                [0, 0],
+                texture.size,
+                4 * width as usize,
+                4 * width as usize * height as usize,
+            )?;
+            Texture::border_color(&texture.settings)?;
+        }
+        if pending.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let device = context.device;
+        let format = context.texture_format();
+        let staging_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Texture Loader Staging Buffer"),
+            contents: &self.data,
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+        let textures = pending
+            .iter()
+            .map(|texture| Texture::create_rgba(device, format, texture.size, 1))
+            .collect::<Vec<_>>();
+
+        context.queue.submit(Some(encode(device, |encoder| {
+            for (texture, wgpu_texture) in pending.iter().zip(&textures) {
+                let [width, height] = texture.size;
+                encoder.copy_buffer_to_texture(
+                    wgpu::ImageCopyBuffer {
+                        buffer: &staging_buffer,
+                        layout: wgpu::ImageDataLayout {
+                            offset: texture.offset,
+                            bytes_per_row: Some(padded_row_size(width) as u32),
+                            rows_per_image: Some(height),
+                        },
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture: wgpu_texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        })));
+
+        Ok(textures
+            .into_iter()
+            .zip(pending)
+            .map(|(wgpu_texture, texture)| {
+                Texture::from_wgpu(
+                    device,
+                    wgpu_texture,
+                    texture.size,
+                    context.sampler(&texture.settings),
+                )
+            })
+            .collect())
+    }
+}
+
+/// Returns the RGBA pixels of `memory`, whose rows are `bytes_per_row` apart,
+/// with rows of `width` pixels padded to the alignment of buffer copies.
+///
+/// `memory` is only copied if its rows aren't aligned already.
+pub(crate) fn pad_rows(
+    memory: &[u8],
+    bytes_per_row: usize,
+    [width, height]: [u32; 2],
+) -> Cow<'_, [u8]> {
+    let row_size = 4 * width as usize;
+    let padded_row_size = padded_row_size(width);
+    if bytes_per_row == padded_row_size {
+        return Cow::Borrowed(memory);
+    }
+    let mut data = vec![0; padded_row_size * height as usize];
+    for (row, padded_row) in memory
+        .chunks(bytes_per_row)
+        .zip(data.chunks_mut(padded_row_size))
+    {
+        padded_row[..row_size].copy_from_slice(&row[..row_size]);
+    }
+    Cow::Owned(data)
+}
+
+/// Returns the size of a row of `width` RGBA pixels, padded to the alignment of buffer copies.
+pub(crate) fn padded_row_size(width: u32) -> usize {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+    (4 * width as usize).div_ceil(align) * align
+}
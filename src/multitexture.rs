@@ -0,0 +1,184 @@
+//! Drawing with two textures per fragment.
+
+use crate::{PsoStencil, Texture};
+use graphics::draw_state::{Blend, Stencil};
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+/// How the second texture of a multitexture draw is combined with the base one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultitextureMode {
+    /// Modulates the base texture by the second one, as for a lightmap or a detail map.
+    Multiply,
+    /// Adds the second texture, weighted by its alpha, to the color of the base one, as for a glow map.
+    Add,
+}
+
+/// Input struct for the "multitexture" pipeline's vertex shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct MultitexturePipelineInput {
+    pub(crate) xy: [f32; 2],
+    pub(crate) uv: [f32; 2],
+    /// Coordinates of the second texture.
+    pub(crate) uv2: [f32; 2],
+}
+
+impl MultitexturePipelineInput {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<MultitexturePipelineInput>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+            ],
+        }
+    }
+}
+
+/// Pipelines sampling a base texture and a second one, with a vertex layout of their own.
+pub(crate) struct MultitexturePipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipelines: PsoStencil<wgpu::RenderPipeline>,
+}
+
+impl MultitexturePipelines {
+    /// Creates new `MultitexturePipelines`, using `f` with a pipeline layout, shader module, vertex layout
+    /// and fragment entry point, for all Stencil and Blend modes.
+    ///
+    /// The base texture is bound with `texture_bind_group_layout`, as for the "textured" pipeline.
+    pub(crate) fn new<F>(
+        device: &wgpu::Device,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        mut f: F,
+    ) -> Self
+    where
+        F: FnMut(
+            &str,
+            &wgpu::PipelineLayout,
+            &wgpu::ShaderModule,
+            &wgpu::VertexBufferLayout,
+            &str,
+            Option<wgpu::BlendState>,
+            wgpu::StencilState,
+        ) -> wgpu::RenderPipeline,
+    {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Multitexture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Multitexture Pipeline Layout"),
+            bind_group_layouts: &[texture_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::include_wgsl!("multitexture.wgsl"));
+
+        let vertex_layout = MultitexturePipelineInput::desc();
+        let pipelines = PsoStencil::new(|blend, stencil| {
+            f(
+                "Multitexture Render Pipeline",
+                &pipeline_layout,
+                &shader_module,
+                &vertex_layout,
+                "fs_main",
+                blend,
+                stencil,
+            )
+        });
+
+        MultitexturePipelines {
+            bind_group_layout,
+            pipelines,
+        }
+    }
+
+    /// Returns the pipeline for `stencil` and `blend`, and the stencil reference value to draw it with.
+    pub(crate) fn stencil_blend(
+        &self,
+        stencil: Option<Stencil>,
+        blend: Option<Blend>,
+    ) -> (&wgpu::RenderPipeline, Option<u8>) {
+        self.pipelines.stencil_blend(stencil, blend)
+    }
+
+    /// Creates the bind group of `second`, combined with the base texture with `mode` by `blend_factor`.
+    pub(crate) fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        second: &Texture,
+        mode: MultitextureMode,
+        blend_factor: f32,
+    ) -> Arc<wgpu::BindGroup> {
+        let mode = match mode {
+            MultitextureMode::Multiply => 0,
+            MultitextureMode::Add => 1,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Multitexture Params Buffer"),
+            contents: bytemuck::bytes_of(&[blend_factor.to_bits(), mode, 0, 0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        Arc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Multitexture Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&second.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&second.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        }))
+    }
+}
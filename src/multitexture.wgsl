@@ -0,0 +1,53 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) uv2: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) uv2: vec2<f32>,
+}
+
+struct Params {
+    blend_factor: f32,
+    // 0 multiplies the base texture by the second one, 1 adds the second one.
+    mode: u32,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+    out.uv = in.uv;
+    out.uv2 = in.uv2;
+    return out;
+}
+
+@group(0)
+@binding(0)
+var base_texture: texture_2d<f32>;
+@group(0)
+@binding(1)
+var base_sampler: sampler;
+@group(1)
+@binding(0)
+var second_texture: texture_2d<f32>;
+@group(1)
+@binding(1)
+var second_sampler: sampler;
+@group(1)
+@binding(2)
+var<uniform> params: Params;
+
+// Modulates the base texture by the second one, or adds the second one, as much as `params.blend_factor`.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base = textureSample(base_texture, base_sampler, in.uv);
+    let second = textureSample(second_texture, second_sampler, in.uv2);
+    if params.mode == 1u {
+        return vec4<f32>(base.rgb + second.rgb * second.a * params.blend_factor, base.a);
+    }
+    return mix(base, base * second, params.blend_factor);
+}
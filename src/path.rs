@@ -0,0 +1,106 @@
+//! Filling of self-intersecting paths through the stencil buffer.
+
+use crate::PsoBlend;
+use graphics::draw_state::Blend;
+
+/// Rule deciding which regions of a self-intersecting path are inside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FillRule {
+    /// Regions the path winds around a non-zero number of times are filled.
+    NonZero,
+    /// Regions the path winds around an odd number of times are filled.
+    EvenOdd,
+}
+
+/// Pipelines filling a path in two steps.
+///
+/// The triangle fan of the path is first drawn into the stencil buffer only, counting its windings.
+/// Its bounding box is then drawn where the stencil value is non-zero, resetting it to 0.
+pub(crate) struct PathPipelines {
+    non_zero: wgpu::RenderPipeline,
+    even_odd: wgpu::RenderPipeline,
+    cover: PsoBlend<wgpu::RenderPipeline>,
+}
+
+impl PathPipelines {
+    /// Creates new `PathPipelines`, using `f`, for all fill rules and Blend modes.
+    pub(crate) fn new<F>(mut f: F) -> Self
+    where
+        F: FnMut(
+            &str,
+            Option<wgpu::BlendState>,
+            wgpu::StencilState,
+            wgpu::ColorWrites,
+        ) -> wgpu::RenderPipeline,
+    {
+        use wgpu::{CompareFunction, StencilFaceState, StencilOperation, StencilState};
+
+        let stencil_winding = |front_op, back_op| StencilState {
+            front: StencilFaceState {
+                pass_op: front_op,
+                ..Default::default()
+            },
+            back: StencilFaceState {
+                pass_op: back_op,
+                ..Default::default()
+            },
+            read_mask: 255,
+            write_mask: 255,
+        };
+        let stencil_cover = StencilState {
+            front: StencilFaceState {
+                compare: CompareFunction::NotEqual,
+                pass_op: StencilOperation::Zero,
+                ..Default::default()
+            },
+            back: StencilFaceState {
+                compare: CompareFunction::NotEqual,
+                pass_op: StencilOperation::Zero,
+                ..Default::default()
+            },
+            read_mask: 255,
+            write_mask: 255,
+        };
+
+        PathPipelines {
+            non_zero: f(
+                "Path Fill Render Pipeline",
+                None,
+                stencil_winding(
+                    StencilOperation::IncrementWrap,
+                    StencilOperation::DecrementWrap,
+                ),
+                wgpu::ColorWrites::empty(),
+            ),
+            even_odd: f(
+                "Path Fill Render Pipeline",
+                None,
+                stencil_winding(StencilOperation::Invert, StencilOperation::Invert),
+                wgpu::ColorWrites::empty(),
+            ),
+            cover: PsoBlend::new(|blend| {
+                f(
+                    "Path Cover Render Pipeline",
+                    blend,
+                    stencil_cover.clone(),
+                    wgpu::ColorWrites::ALL,
+                )
+            }),
+        }
+    }
+
+    /// Returns the pipeline counting windings for `fill_rule`.
+    pub(crate) fn fill(&self, fill_rule: FillRule) -> &wgpu::RenderPipeline {
+        match fill_rule {
+            FillRule::NonZero => &self.non_zero,
+            FillRule::EvenOdd => &self.even_odd,
+        }
+    }
+
+    /// Returns the pipeline covering the counted windings with `blend`.
+    ///
+    /// It must be drawn with a stencil reference of 0.
+    pub(crate) fn cover(&self, blend: Option<Blend>) -> &wgpu::RenderPipeline {
+        self.cover.blend(blend)
+    }
+}
@@ -0,0 +1,301 @@
+//! Text rendering with signed distance field glyphs, which stay crisp at any scale.
+
+use crate::{
+    quad, transform_position, BatchKind, Texture, TextureContext, TextureError,
+    TexturedPipelineInput, WgpuGraphics,
+};
+use graphics::{
+    types::{Color, Matrix2d},
+    DrawState,
+};
+use std::{collections::HashMap, io, path::Path};
+use texture::TextureSettings;
+
+/// Font size, in pixels, glyphs are rasterized at.
+const BASE_SIZE: f32 = 48.0;
+/// Distance, in pixels at the base size, covered by the distance field on each side of glyph edges.
+const SPREAD: u32 = 6;
+/// Width and height of the glyph atlas texture.
+const ATLAS_SIZE: u32 = 1024;
+
+/// A glyph quad, as a `[x, y, width, height]` rectangle and a normalized rectangle in the atlas.
+type GlyphQuad = ([f32; 4], [f32; 4]);
+
+/// Placement of a glyph's distance field in the atlas.
+#[derive(Debug, Clone, Copy)]
+struct SdfBitmap {
+    /// Normalized `[x, y, width, height]` in the atlas.
+    uv_rect: [f32; 4],
+    /// Top-left corner from the pen position, in pixels at the base size.
+    offset: [f32; 2],
+    /// Size in pixels at the base size.
+    size: [f32; 2],
+}
+
+/// A glyph loaded into a [`SdfGlyphCache`].
+#[derive(Debug, Clone, Copy)]
+struct SdfGlyph {
+    advance_width: f32,
+    /// `None` for glyphs without outline, such as spaces.
+    bitmap: Option<SdfBitmap>,
+}
+
+/// Stores signed distance fields of glyphs for text rendering, in a single-channel texture.
+///
+/// Unlike [`GlyphCache`](`crate::GlyphCache`), glyphs are rasterized once at a fixed size,
+/// and drawn by [`WgpuGraphics::draw_sdf_text`](`crate::WgpuGraphics::draw_sdf_text`) at any size without blurring.
+pub struct SdfGlyphCache<'a> {
+    /// The factory used to create and update the atlas texture.
+    pub factory: TextureContext<'a>,
+    font: rusttype::Font<'static>,
+    texture: Texture,
+    glyphs: HashMap<char, SdfGlyph>,
+    cursor: [u32; 2],
+    shelf_height: u32,
+}
+
+impl<'a> SdfGlyphCache<'a> {
+    /// Creates a new `SdfGlyphCache` with the font at `font`.
+    pub fn new<P>(font: P, factory: TextureContext<'a>) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let data = std::fs::read(font)?;
+        SdfGlyphCache::from_bytes(&data, factory)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid font data"))
+    }
+
+    /// Creates a new `SdfGlyphCache` with the font in `font`, or returns `None` if it isn't a valid font.
+    pub fn from_bytes(font: &[u8], mut factory: TextureContext<'a>) -> Option<Self> {
+        let font = rusttype::Font::try_from_vec(font.to_vec())?;
+        let texture = factory.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("SDF Glyph Texture"),
+            size: wgpu::Extent3d {
+                width: ATLAS_SIZE,
+                height: ATLAS_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let texture = Texture::from_wgpu(
+            factory.device,
+            texture,
+            [ATLAS_SIZE, ATLAS_SIZE],
+            factory.sampler(&TextureSettings::new()),
+        );
+        Some(SdfGlyphCache {
+            factory,
+            font,
+            texture,
+            glyphs: HashMap::new(),
+            cursor: [0, 0],
+            shelf_height: 0,
+        })
+    }
+
+    /// Returns the atlas texture, whose red channel holds the distance fields.
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Returns the quads of the glyphs of `text` at `font_size`, as pairs of `[x, y, width, height]` rectangles,
+    /// relative to the start of the baseline, and normalized rectangles in the atlas.
+    ///
+    /// Glyphs are loaded into the atlas on first use.
+    /// Returns an error if the atlas is full.
+    pub(crate) fn layout(
+        &mut self,
+        text: &str,
+        font_size: f32,
+    ) -> Result<Vec<GlyphQuad>, TextureError> {
+        let scale = font_size / BASE_SIZE;
+        let mut x = 0.0;
+        let mut quads = Vec::with_capacity(text.len());
+        for ch in text.chars() {
+            let glyph = self.glyph(ch)?;
+            if let Some(bitmap) = glyph.bitmap {
+                quads.push((
+                    [
+                        x + bitmap.offset[0] * scale,
+                        bitmap.offset[1] * scale,
+                        bitmap.size[0] * scale,
+                        bitmap.size[1] * scale,
+                    ],
+                    bitmap.uv_rect,
+                ));
+            }
+            x += glyph.advance_width * scale;
+        }
+        Ok(quads)
+    }
+
+    /// Returns the glyph of `ch`, loading it into the atlas if needed.
+    fn glyph(&mut self, ch: char) -> Result<SdfGlyph, TextureError> {
+        if let Some(&glyph) = self.glyphs.get(&ch) {
+            return Ok(glyph);
+        }
+
+        let glyph = self
+            .font
+            .glyph(ch)
+            .scaled(rusttype::Scale::uniform(BASE_SIZE));
+        let advance_width = glyph.h_metrics().advance_width;
+        let glyph = glyph.positioned(rusttype::point(0.0, 0.0));
+        let bitmap = match glyph.pixel_bounding_box() {
+            Some(bounds) => {
+                let size = [bounds.width() as u32, bounds.height() as u32];
+                let mut coverage = vec![0.0; (size[0] * size[1]) as usize];
+                glyph.draw(|x, y, v| coverage[(y * size[0] + x) as usize] = v);
+                let mut bitmap = self.upload(&distance_field(&coverage, size), size)?;
+                bitmap.offset = [
+                    (bounds.min.x - SPREAD as i32) as f32,
+                    (bounds.min.y - SPREAD as i32) as f32,
+                ];
+                Some(bitmap)
+            }
+            None => None,
+        };
+
+        let glyph = SdfGlyph {
+            advance_width,
+            bitmap,
+        };
+        self.glyphs.insert(ch, glyph);
+        Ok(glyph)
+    }
+
+    /// Uploads the distance `field` of a glyph of `size` into the next free region of the atlas.
+    fn upload(
+        &mut self,
+        field: &[u8],
+        [width, height]: [u32; 2],
+    ) -> Result<SdfBitmap, TextureError> {
+        let size = [width + 2 * SPREAD, height + 2 * SPREAD];
+        if self.cursor[0] + size[0] > ATLAS_SIZE {
+            self.cursor = [0, self.cursor[1] + self.shelf_height];
+            self.shelf_height = 0;
+        }
+        if self.cursor[0] + size[0] > ATLAS_SIZE || self.cursor[1] + size[1] > ATLAS_SIZE {
+            return Err(TextureError::OutOfBounds {
+                offset: self.cursor,
+                size,
+            });
+        }
+        let [x, y] = self.cursor;
+        self.cursor[0] += size[0];
+        self.shelf_height = self.shelf_height.max(size[1]);
+
+        self.factory.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            field,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size[0]),
+                rows_per_image: Some(size[1]),
+            },
+            wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let atlas_size = ATLAS_SIZE as f32;
+        Ok(SdfBitmap {
+            uv_rect: [
+                x as f32 / atlas_size,
+                y as f32 / atlas_size,
+                size[0] as f32 / atlas_size,
+                size[1] as f32 / atlas_size,
+            ],
+            offset: [0.0; 2],
+            size: [size[0] as f32, size[1] as f32],
+        })
+    }
+}
+
+impl<'a> WgpuGraphics<'a> {
+    /// Draws `text` with glyphs of `cache` at `font_size`, starting at the origin of `transform` on the baseline.
+    ///
+    /// Glyphs are covered where their distance field is above `threshold`, which is 0.5 on their edges.
+    /// Lower values draw bolder glyphs, so drawing the same text with a lower threshold first, in another color,
+    /// outlines it.
+    /// Returns an error if the glyphs don't fit in the atlas of `cache`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_sdf_text(
+        &mut self,
+        draw_state: &DrawState,
+        cache: &mut SdfGlyphCache,
+        text: &str,
+        font_size: f32,
+        &color: &Color,
+        transform: Matrix2d,
+        threshold: f32,
+    ) -> Result<(), TextureError> {
+        let quads = cache.layout(text, font_size)?;
+        let vertex = |x: f32, y: f32, uv: [f32; 2]| TexturedPipelineInput {
+            xy: transform_position(transform, [x as f64, y as f64]),
+            uv,
+            color,
+            alpha_test: threshold,
+        };
+
+        let start = self.textured_data.len() as u32;
+        for ([x, y, w, h], [u, v, uw, vh]) in quads {
+            self.textured_data.extend_from_slice(&quad(
+                vertex(x, y, [u, v]),
+                vertex(x + w, y, [u + uw, v]),
+                vertex(x + w, y + h, [u + uw, v + vh]),
+                vertex(x, y + h, [u, v + vh]),
+            ));
+        }
+        let end = self.textured_data.len() as u32;
+        self.push_batch(
+            BatchKind::Sdf(cache.texture().bind_group.clone()),
+            draw_state,
+            start..end,
+        );
+        Ok(())
+    }
+}
+
+/// Computes the signed distance field of a glyph of `size` from its `coverage`, padded by `SPREAD` on each side.
+///
+/// Values are 0.5 on edges, increasing inside the glyph and decreasing outside of it.
+fn distance_field(coverage: &[f32], [width, height]: [u32; 2]) -> Vec<u8> {
+    let spread = SPREAD as i32;
+    let (width, height) = (width as i32, height as i32);
+    let inside = |x: i32, y: i32| {
+        x >= 0 && y >= 0 && x < width && y < height && coverage[(y * width + x) as usize] >= 0.5
+    };
+
+    let mut field = Vec::with_capacity(((width + 2 * spread) * (height + 2 * spread)) as usize);
+    for y in -spread..height + spread {
+        for x in -spread..width + spread {
+            let is_inside = inside(x, y);
+            let mut min_distance_sq = spread * spread;
+            for dy in -spread..=spread {
+                for dx in -spread..=spread {
+                    if inside(x + dx, y + dy) != is_inside {
+                        min_distance_sq = min_distance_sq.min(dx * dx + dy * dy);
+                    }
+                }
+            }
+            // Distances are between pixel centers, while the edge is half a pixel away.
+            let distance = ((min_distance_sq as f32).sqrt() - 0.5).max(0.0) / SPREAD as f32;
+            let signed_distance = if is_inside { distance } else { -distance };
+            field.push(((0.5 + 0.5 * signed_distance) * 255.0).round() as u8);
+        }
+    }
+    field
+}
@@ -0,0 +1,39 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) threshold: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) threshold: f32,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+    out.uv = in.uv;
+    out.color = in.color;
+    out.threshold = in.threshold;
+    return out;
+}
+
+@group(0)
+@binding(0)
+var texture: texture_2d<f32>;
+@group(0)
+@binding(1)
+var s_sampler: sampler;
+
+// Covers where the distance is above `threshold`, antialiased over about a pixel at any scale.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let distance = textureSample(texture, s_sampler, in.uv).r;
+    let smoothing = max(fwidth(distance) * 0.5, 0.0001);
+    let coverage = smoothstep(in.threshold - smoothing, in.threshold + smoothing, distance);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,386 @@
+//! Drop shadows, rendered by blurring an offscreen layer and compositing it behind the content,
+//! and group opacity, rendered by compositing an offscreen layer with a uniform opacity.
+
+use std::cell::{RefCell, RefMut};
+use wgpu::util::DeviceExt;
+
+/// Uniforms of the "shadow" shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowParams {
+    color: [f32; 4],
+    offset: [f32; 2],
+    direction: [f32; 2],
+    radius: f32,
+    _padding: [f32; 3],
+}
+
+/// Offscreen textures a shadow is rendered into.
+struct ShadowTargets {
+    size: [u32; 2],
+    layer: wgpu::TextureView,
+    /// Multisampled texture the layer is drawn into and resolved from, with multisampling.
+    multisampled_layer: Option<wgpu::TextureView>,
+    blurred: wgpu::TextureView,
+    stencil: wgpu::TextureView,
+}
+
+impl ShadowTargets {
+    fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        stencil_format: wgpu::TextureFormat,
+        sample_count: u32,
+        [width, height]: [u32; 2],
+    ) -> Self {
+        let create_view = |label, format, sample_count| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        ShadowTargets {
+            size: [width, height],
+            layer: create_view("Shadow Layer Texture", format, 1),
+            multisampled_layer: if sample_count > 1 {
+                Some(create_view(
+                    "Shadow Multisampled Layer Texture",
+                    format,
+                    sample_count,
+                ))
+            } else {
+                None
+            },
+            blurred: create_view("Shadow Blur Texture", format, 1),
+            stencil: create_view("Shadow Stencil Texture", stencil_format, sample_count),
+        }
+    }
+
+    /// Returns the view the layer is drawn into, and the view it is resolved to, with multisampling.
+    fn layer_attachment(&self) -> (&wgpu::TextureView, Option<&wgpu::TextureView>) {
+        match &self.multisampled_layer {
+            Some(multisampled_layer) => (multisampled_layer, Some(&self.layer)),
+            None => (&self.layer, None),
+        }
+    }
+}
+
+/// Pipelines used to blur a layer and composite it as a shadow, or composite it with an opacity.
+pub(crate) struct ShadowPipelines {
+    format: wgpu::TextureFormat,
+    stencil_format: wgpu::TextureFormat,
+    sample_count: u32,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    blur: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+    opacity: wgpu::RenderPipeline,
+    targets: RefCell<Option<ShadowTargets>>,
+}
+
+impl ShadowPipelines {
+    /// Creates new `ShadowPipelines` rendering into `format`, with stencil buffers of `stencil_format`,
+    /// and compositing into render targets with `sample_count` samples.
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        stencil_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::include_wgsl!("shadow.wgsl"));
+
+        let create_pipeline = |label, entry_point, blend: Option<wgpu::BlendState>, count| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count,
+                    ..Default::default()
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            })
+        };
+
+        ShadowPipelines {
+            format,
+            stencil_format,
+            sample_count,
+            blur: create_pipeline("Shadow Blur Render Pipeline", "fs_blur", None, 1),
+            composite: create_pipeline(
+                "Shadow Composite Render Pipeline",
+                "fs_shadow",
+                Some(wgpu::BlendState::ALPHA_BLENDING),
+                sample_count,
+            ),
+            opacity: create_pipeline(
+                "Group Opacity Render Pipeline",
+                "fs_opacity",
+                Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                sample_count,
+            ),
+            bind_group_layout,
+            sampler,
+            targets: RefCell::new(None),
+        }
+    }
+
+    /// Encodes a shadow onto `output_view`.
+    ///
+    /// `draw_layer` renders the shape casting the shadow, given a color view of `size` to clear,
+    /// the view to resolve it to with multisampling, and a stencil view to clear.
+    /// The layer is then blurred by `blur` pixels, and composited with `color`, moved by `offset` pixels.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn encode<F>(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        size: [u32; 2],
+        offset: [f32; 2],
+        blur: f32,
+        color: [f32; 4],
+        draw_layer: F,
+    ) where
+        F: FnOnce(
+            &mut wgpu::CommandEncoder,
+            &wgpu::TextureView,
+            Option<&wgpu::TextureView>,
+            &wgpu::TextureView,
+        ),
+    {
+        let targets = self.targets(device, size);
+        let [width, height] = size;
+
+        let (layer_view, resolve_target) = targets.layer_attachment();
+        draw_layer(encoder, layer_view, resolve_target, &targets.stencil);
+
+        let params = ShadowParams {
+            color,
+            offset: [0.0; 2],
+            direction: [0.0; 2],
+            radius: blur,
+            _padding: [0.0; 3],
+        };
+        self.pass(
+            device,
+            encoder,
+            &self.blur,
+            &targets.layer,
+            &targets.blurred,
+            wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            ShadowParams {
+                direction: [1.0 / width as f32, 0.0],
+                ..params
+            },
+        );
+        self.pass(
+            device,
+            encoder,
+            &self.blur,
+            &targets.blurred,
+            &targets.layer,
+            wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            ShadowParams {
+                direction: [0.0, 1.0 / height as f32],
+                ..params
+            },
+        );
+        self.pass(
+            device,
+            encoder,
+            &self.composite,
+            &targets.layer,
+            output_view,
+            wgpu::LoadOp::Load,
+            ShadowParams {
+                offset: [
+                    2.0 * offset[0] / width as f32,
+                    -2.0 * offset[1] / height as f32,
+                ],
+                ..params
+            },
+        );
+    }
+
+    /// Encodes a layer onto `output_view`, with its opacity multiplied by `alpha`.
+    ///
+    /// `draw_layer` renders the layer, given views as for [`ShadowPipelines::encode`].
+    /// Overlapping shapes of the layer are blended together first, so they fade as one.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn encode_opacity<F>(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        size: [u32; 2],
+        alpha: f32,
+        draw_layer: F,
+    ) where
+        F: FnOnce(
+            &mut wgpu::CommandEncoder,
+            &wgpu::TextureView,
+            Option<&wgpu::TextureView>,
+            &wgpu::TextureView,
+        ),
+    {
+        let targets = self.targets(device, size);
+
+        let (layer_view, resolve_target) = targets.layer_attachment();
+        draw_layer(encoder, layer_view, resolve_target, &targets.stencil);
+
+        self.pass(
+            device,
+            encoder,
+            &self.opacity,
+            &targets.layer,
+            output_view,
+            wgpu::LoadOp::Load,
+            ShadowParams {
+                color: [1.0, 1.0, 1.0, alpha],
+                offset: [0.0; 2],
+                direction: [0.0; 2],
+                radius: 0.0,
+                _padding: [0.0; 3],
+            },
+        );
+    }
+
+    /// Returns the offscreen textures, recreating them first if they aren't of `size`.
+    fn targets(&self, device: &wgpu::Device, size: [u32; 2]) -> RefMut<'_, ShadowTargets> {
+        let mut targets = self.targets.borrow_mut();
+        if targets.as_ref().is_none_or(|targets| targets.size != size) {
+            *targets = Some(ShadowTargets::new(
+                device,
+                self.format,
+                self.stencil_format,
+                self.sample_count,
+                size,
+            ));
+        }
+        RefMut::map(targets, |targets| targets.as_mut().unwrap())
+    }
+
+    /// Encodes a render pass drawing `source` onto `target` with `pipeline`.
+    #[allow(clippy::too_many_arguments)]
+    fn pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        source: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+        params: ShadowParams,
+    ) {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
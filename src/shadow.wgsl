@@ -0,0 +1,65 @@
+struct Params {
+    color: vec4<f32>,
+    offset: vec2<f32>,
+    direction: vec2<f32>,
+    radius: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0)
+@binding(0)
+var texture: texture_2d<f32>;
+@group(0)
+@binding(1)
+var s_sampler: sampler;
+@group(0)
+@binding(2)
+var<uniform> params: Params;
+
+// Draws a triangle covering the whole target, moved by `params.offset`.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(
+        uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0) + params.offset,
+        0.0,
+        1.0,
+    );
+    out.uv = uv;
+    return out;
+}
+
+// Gaussian blur along `params.direction`, which is the size of a texel.
+@fragment
+fn fs_blur(in: VertexOutput) -> @location(0) vec4<f32> {
+    let sigma = max(params.radius * 0.5, 0.0001);
+    let radius = i32(ceil(params.radius));
+    var sum = vec4<f32>(0.0);
+    var weight_sum = 0.0;
+    for (var i = -radius; i <= radius; i = i + 1) {
+        let x = f32(i);
+        let weight = exp(-(x * x) / (2.0 * sigma * sigma));
+        sum = sum + textureSampleLevel(texture, s_sampler, in.uv + params.direction * x, 0.0) * weight;
+        weight_sum = weight_sum + weight;
+    }
+    return sum / weight_sum;
+}
+
+// Fills with `params.color`, using the alpha channel of the texture as coverage.
+@fragment
+fn fs_shadow(in: VertexOutput) -> @location(0) vec4<f32> {
+    let inside = all(in.uv >= vec2<f32>(0.0)) && all(in.uv <= vec2<f32>(1.0));
+    let alpha = textureSampleLevel(texture, s_sampler, in.uv, 0.0).a;
+    return vec4<f32>(params.color.rgb, params.color.a * alpha * select(0.0, 1.0, inside));
+}
+
+// Draws the texture, which has premultiplied alpha, with its opacity multiplied by `params.color.a`.
+@fragment
+fn fs_opacity(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(texture, s_sampler, in.uv, 0.0) * params.color.a;
+}
@@ -0,0 +1,111 @@
+//! Texture updates uploaded through a staging belt, for textures updated every frame.
+
+use crate::loader::padded_row_size;
+
+/// Texture updates recorded during a frame, and the staging memory reused across frames to upload them.
+pub(crate) struct StagedUploads {
+    staging_belt: wgpu::util::StagingBelt,
+    /// Buffer the updates are copied to textures from, which grows on demand.
+    buffer: wgpu::Buffer,
+    /// Offset, in `buffer`, of the next update.
+    offset: wgpu::BufferAddress,
+    encoder: Option<wgpu::CommandEncoder>,
+}
+
+impl StagedUploads {
+    /// Creates new `StagedUploads`, allocating staging memory by chunks of `chunk_size` bytes.
+    pub(crate) fn new(device: &wgpu::Device, chunk_size: wgpu::BufferAddress) -> Self {
+        let chunk_size = chunk_size.max(wgpu::COPY_BUFFER_ALIGNMENT);
+        StagedUploads {
+            staging_belt: wgpu::util::StagingBelt::new(chunk_size),
+            buffer: create_buffer(device, chunk_size),
+            offset: 0,
+            encoder: None,
+        }
+    }
+
+    /// Records an update of the `size` rectangle at `origin` of `texture` with the RGBA pixels of `memory`,
+    /// whose rows are `bytes_per_row` apart.
+    pub(crate) fn write(
+        &mut self,
+        device: &wgpu::Device,
+        texture: &wgpu::Texture,
+        origin: wgpu::Origin3d,
+        [width, height]: [u32; 2],
+        memory: &[u8],
+        bytes_per_row: usize,
+    ) {
+        let row_size = 4 * width as usize;
+        let padded_row_size = padded_row_size(width);
+        let size = match wgpu::BufferSize::new((padded_row_size * height as usize) as u64) {
+            Some(size) => size,
+            None => return,
+        };
+        if self.offset + size.get() > self.buffer.size() {
+            // Copies recorded so far keep the previous buffer alive until they are done.
+            let buffer_size = size.get().max(2 * self.buffer.size()).next_power_of_two();
+            self.buffer = create_buffer(device, buffer_size);
+            self.offset = 0;
+        }
+
+        let encoder = self.encoder.get_or_insert_with(|| {
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Texture Upload Command Encoder"),
+            })
+        });
+        {
+            let mut view =
+                self.staging_belt
+                    .write_buffer(encoder, &self.buffer, self.offset, size, device);
+            for (row, padded_row) in memory
+                .chunks(bytes_per_row)
+                .zip(view.chunks_mut(padded_row_size))
+            {
+                padded_row[..row_size].copy_from_slice(&row[..row_size]);
+            }
+        }
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: self.offset,
+                    bytes_per_row: Some(padded_row_size as u32),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.offset += size.get();
+    }
+
+    /// Submits the updates recorded since the last call to `queue`, and recalls the staging memory.
+    pub(crate) fn finish(&mut self, queue: &wgpu::Queue) {
+        self.offset = 0;
+        let encoder = match self.encoder.take() {
+            Some(encoder) => encoder,
+            None => return,
+        };
+        self.staging_belt.finish();
+        queue.submit(Some(encoder.finish()));
+        self.staging_belt.recall();
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Texture Upload Buffer"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
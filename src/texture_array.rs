@@ -0,0 +1,214 @@
+//! Textures of several layers of the same size, drawn in one batch with a layer per vertex.
+
+use crate::{PsoStencil, Texture, TextureContext, TextureError};
+use graphics::draw_state::{Blend, Stencil};
+use graphics::ImageSize;
+use std::sync::Arc;
+use texture::TextureSettings;
+
+/// A texture of several layers of the same size, such as the tiles of a tile map.
+///
+/// Triangles sampling different layers are drawn in one batch by [`WgpuGraphics::draw_texture_array`](crate::WgpuGraphics::draw_texture_array),
+/// instead of one per texture.
+pub struct TextureArray {
+    texture: wgpu::Texture,
+    bind_group: Arc<wgpu::BindGroup>,
+    width: u32,
+    height: u32,
+}
+
+impl TextureArray {
+    /// Creates a `TextureArray` with a layer per image of `images`, which must all have the same size.
+    pub fn from_images(
+        context: &mut TextureContext,
+        images: &[image::RgbaImage],
+        settings: &TextureSettings,
+    ) -> Result<Self, TextureError> {
+        let [width, height] = images
+            .first()
+            .map_or([0, 0], |image| [image.width(), image.height()]);
+        let limits = context.device.limits();
+        let layers = images.len() as u32;
+        if layers > limits.max_texture_array_layers {
+            context.failed_uploads += 1;
+            return Err(TextureError::InvalidLayer(layers - 1));
+        }
+        for image in images {
+            let size = [image.width(), image.height()];
+            if size != [width, height] {
+                context.failed_uploads += 1;
+                return Err(TextureError::InvalidSize(size));
+            }
+        }
+        context.check_upload(
+            [width, height],
+            [0, 0],
+            [width, height],
+            4 * width as usize,
+            4 * width as usize * height as usize,
+        )?;
+        Texture::border_color(settings)?;
+
+        let (device, queue) = (context.device, context.queue);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Texture Array"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: context.texture_format(),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        for (layer, image) in images.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                image.as_raw(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * width),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Texture Array View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = context.sampler(settings);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Texture Array Bind Group"),
+            layout: &create_bind_group_layout(device),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Ok(TextureArray {
+            texture,
+            bind_group: Arc::new(bind_group),
+            width,
+            height,
+        })
+    }
+
+    /// Returns the number of layers.
+    pub fn layers(&self) -> u32 {
+        self.texture.depth_or_array_layers()
+    }
+
+    /// Returns the bind group sampling the layers.
+    pub(crate) fn bind_group(&self) -> Arc<wgpu::BindGroup> {
+        self.bind_group.clone()
+    }
+}
+
+impl ImageSize for TextureArray {
+    fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+/// Creates the layout of the bind group of a texture array and its sampler.
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Texture Array Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Pipelines sampling texture arrays with the "textured" vertex layout,
+/// whose last attribute holds the layer instead of the alpha threshold.
+pub(crate) struct TextureArrayPipelines {
+    pipelines: PsoStencil<wgpu::RenderPipeline>,
+}
+
+impl TextureArrayPipelines {
+    /// Creates new `TextureArrayPipelines`, using `f` with a pipeline layout, shader module and fragment entry point,
+    /// for all Stencil and Blend modes.
+    pub(crate) fn new<F>(device: &wgpu::Device, mut f: F) -> Self
+    where
+        F: FnMut(
+            &str,
+            &wgpu::PipelineLayout,
+            &wgpu::ShaderModule,
+            &str,
+            Option<wgpu::BlendState>,
+            wgpu::StencilState,
+        ) -> wgpu::RenderPipeline,
+    {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Texture Array Pipeline Layout"),
+            bind_group_layouts: &[&create_bind_group_layout(device)],
+            push_constant_ranges: &[],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::include_wgsl!("texture_array.wgsl"));
+
+        let pipelines = PsoStencil::new(|blend, stencil| {
+            f(
+                "Texture Array Render Pipeline",
+                &pipeline_layout,
+                &shader_module,
+                "fs_main",
+                blend,
+                stencil,
+            )
+        });
+
+        TextureArrayPipelines { pipelines }
+    }
+
+    /// Returns the pipeline for `stencil` and `blend`, and the stencil reference value to draw it with.
+    pub(crate) fn stencil_blend(
+        &self,
+        stencil: Option<Stencil>,
+        blend: Option<Blend>,
+    ) -> (&wgpu::RenderPipeline, Option<u8>) {
+        self.pipelines.stencil_blend(stencil, blend)
+    }
+}
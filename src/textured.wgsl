@@ -2,12 +2,14 @@ struct VertexInput {
     @location(0) position: vec2<f32>,
     @location(1) uv: vec2<f32>,
     @location(2) color: vec4<f32>,
+    @location(3) alpha_test: f32,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) uv: vec2<f32>,
     @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) alpha_test: f32,
 }
 
 @vertex
@@ -16,6 +18,7 @@ fn vs_main(in: VertexInput) -> VertexOutput {
     out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
     out.uv = in.uv;
     out.color = in.color;
+    out.alpha_test = in.alpha_test;
     return out;
 }
 
@@ -26,7 +29,49 @@ var texture: texture_2d<f32>;
 @binding(1)
 var s_sampler: sampler;
 
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+// Fragments below the alpha test threshold are discarded in each entry point,
+// as the GLSL back-end also writes helper functions into vertex shaders, where `discard` isn't allowed.
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture, s_sampler, in.uv) * in.color;
+    let color = textureSample(texture, s_sampler, in.uv) * in.color;
+    if color.a < in.alpha_test {
+        discard;
+    }
+    return color;
+}
+
+// Same as `fs_main`, with the tint color converted from sRGB to linear, like the sampled texel.
+@fragment
+fn fs_linear_tint(in: VertexOutput) -> @location(0) vec4<f32> {
+    let tint = vec4<f32>(srgb_to_linear(in.color.rgb), in.color.a);
+    let color = textureSample(texture, s_sampler, in.uv) * tint;
+    if color.a < in.alpha_test {
+        discard;
+    }
+    return color;
+}
+
+// Same as `fs_main`, with alpha premultiplied into the color, for premultiplied alpha blending.
+@fragment
+fn fs_premultiplied(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(texture, s_sampler, in.uv) * in.color;
+    if color.a < in.alpha_test {
+        discard;
+    }
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
+
+// Same as `fs_linear_tint`, with alpha premultiplied into the color, for premultiplied alpha blending.
+@fragment
+fn fs_linear_tint_premultiplied(in: VertexOutput) -> @location(0) vec4<f32> {
+    let tint = vec4<f32>(srgb_to_linear(in.color.rgb), in.color.a);
+    let color = textureSample(texture, s_sampler, in.uv) * tint;
+    if color.a < in.alpha_test {
+        discard;
+    }
+    return vec4<f32>(color.rgb * color.a, color.a);
 }
@@ -0,0 +1,135 @@
+//! GPU timing of render passes, with timestamp queries written around them.
+
+use std::{cell::RefCell, time::Duration};
+
+/// Maximum number of passes timed per frame; further passes aren't timed.
+const MAX_TIMED_PASSES: u32 = 64;
+
+/// Size in bytes of a resolved timestamp.
+const TIMESTAMP_SIZE: wgpu::BufferAddress = std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+/// GPU time taken by a pass of a frame, returned by [`Wgpu2d::gpu_timings`](crate::Wgpu2d::gpu_timings).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassTiming {
+    /// Label of the pass: "Draws", "Shadow" or "Group".
+    pub label: &'static str,
+    /// Time between the start and the end of the pass on the GPU.
+    pub duration: Duration,
+}
+
+/// Timestamp queries written around the passes of a frame, and the buffers they are read back through.
+pub(crate) struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    /// Labels of the passes timed in the frame being encoded.
+    labels: RefCell<Vec<&'static str>>,
+    /// Labels of the passes timed in the last encoded frame, whose timestamps are copied into `readback_buffer`.
+    resolved: RefCell<Vec<&'static str>>,
+}
+
+impl GpuTimer {
+    /// Creates a new `GpuTimer`, for a device with `TIMESTAMP_QUERY`.
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        let count = 2 * MAX_TIMED_PASSES;
+        let size = count as wgpu::BufferAddress * TIMESTAMP_SIZE;
+        GpuTimer {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Timestamp Query Set"),
+                ty: wgpu::QueryType::Timestamp,
+                count,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Resolve Buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Readback Buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            labels: RefCell::new(vec![]),
+            resolved: RefCell::new(vec![]),
+        }
+    }
+
+    /// Encodes the pass `label` with `f`, between timestamps if the frame has queries left.
+    pub(crate) fn time<F>(&self, encoder: &mut wgpu::CommandEncoder, label: &'static str, f: F)
+    where
+        F: FnOnce(&mut wgpu::CommandEncoder),
+    {
+        let mut labels = self.labels.borrow_mut();
+        let index = labels.len() as u32;
+        if index >= MAX_TIMED_PASSES {
+            drop(labels);
+            f(encoder);
+            return;
+        }
+        labels.push(label);
+        drop(labels);
+
+        encoder.write_timestamp(&self.query_set, 2 * index);
+        f(encoder);
+        encoder.write_timestamp(&self.query_set, 2 * index + 1);
+    }
+
+    /// Resolves the timestamps of the frame, and copies them into the readback buffer.
+    pub(crate) fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        let labels = std::mem::take(&mut *self.labels.borrow_mut());
+        if !labels.is_empty() {
+            let count = 2 * labels.len() as u32;
+            encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+            encoder.copy_buffer_to_buffer(
+                &self.resolve_buffer,
+                0,
+                &self.readback_buffer,
+                0,
+                count as wgpu::BufferAddress * TIMESTAMP_SIZE,
+            );
+        }
+        *self.resolved.borrow_mut() = labels;
+    }
+
+    /// Reads back the timings of the last encoded frame, waiting for the GPU to finish it.
+    pub(crate) fn read(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<PassTiming>, wgpu::BufferAsyncError> {
+        let labels = self.resolved.borrow();
+        if labels.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let slice = self
+            .readback_buffer
+            .slice(..2 * labels.len() as wgpu::BufferAddress * TIMESTAMP_SIZE);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+        let period = queue.get_timestamp_period() as f64;
+        let timings = {
+            let data = slice.get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            labels
+                .iter()
+                .zip(timestamps.chunks(2))
+                .map(|(&label, pair)| PassTiming {
+                    label,
+                    duration: Duration::from_nanos(
+                        (pair[1].saturating_sub(pair[0]) as f64 * period) as u64,
+                    ),
+                })
+                .collect()
+        };
+        self.readback_buffer.unmap();
+        Ok(timings)
+    }
+}
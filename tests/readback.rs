@@ -0,0 +1,541 @@
+//! Headless tests drawing into render targets and reading the frames back.
+//!
+//! They need an adapter, so they are ignored unless run with `cargo test -- --ignored`,
+//! which fails them on machines without any.
+
+use graphics::{
+    clear, draw_state::Blend, Context, DrawState, Graphics, Image, Rectangle, Text, Transformed,
+    Viewport,
+};
+use texture::{Filter, TextureSettings};
+use wgpu_graphics::{
+    CachedLayer, GlyphCache, MultitextureMode, Texture, TextureContext, TextureError, Wgpu2d,
+    Wgpu2dSettings, WgpuGraphics,
+};
+
+/// Format of the render targets, which doesn't convert colors so pixels can be compared to vertex colors.
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Returns the device and queue of the default adapter, panicking if there is no adapter.
+fn device() -> (wgpu::Device, wgpu::Queue) {
+    let instance = wgpu::Instance::default();
+    let adapter = futures::executor::block_on(
+        instance.request_adapter(&wgpu::RequestAdapterOptions::default()),
+    )
+    .expect("no adapter to run the readback tests on");
+    futures::executor::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu_graphics::REQUIRED_FEATURES,
+            limits: wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits()),
+        },
+        None,
+    ))
+    .unwrap()
+}
+
+/// Returns the configuration of a surface of `format` and `size`, which the pipelines are built for.
+fn config(format: wgpu::TextureFormat, [width, height]: [u32; 2]) -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width,
+        height,
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+        view_formats: vec![],
+    }
+}
+
+/// Draws `f` into a render target of `format` and `size`, with `settings`, and reads it back.
+fn render<F>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    settings: &Wgpu2dSettings,
+    format: wgpu::TextureFormat,
+    size: [u32; 2],
+    f: F,
+) -> image::RgbaImage
+where
+    F: FnOnce(Context, &mut WgpuGraphics),
+{
+    let wgpu2d = Wgpu2d::with_settings(device, &config(format, size), settings);
+    let target = wgpu2d.create_render_target(device, size, &TextureSettings::new());
+    let command_buffer = wgpu2d.draw_to_texture(device, &target, f).unwrap();
+    queue.submit(Some(command_buffer));
+    Wgpu2d::capture(device, queue, target.as_wgpu()).unwrap()
+}
+
+/// Creates a texture with the RGBA pixels of `data`, sampled with nearest filtering.
+fn texture(context: &mut TextureContext, size: [u32; 2], data: &[u8]) -> Texture {
+    let image = image::RgbaImage::from_raw(size[0], size[1], data.to_vec()).unwrap();
+    let settings = TextureSettings::new().filter(Filter::Nearest);
+    Texture::from_image(context, &image, &settings).unwrap()
+}
+
+/// Asserts that the pixel at `[x, y]` of `image` is `expected`, give or take `tolerance` on each channel.
+#[track_caller]
+fn assert_pixel(image: &image::RgbaImage, [x, y]: [u32; 2], expected: [u8; 4], tolerance: u8) {
+    let actual = image.get_pixel(x, y).0;
+    assert!(
+        actual
+            .iter()
+            .zip(&expected)
+            .all(|(&a, &e)| a.abs_diff(e) <= tolerance),
+        "pixel at {:?} is {:?}, expected {:?}",
+        [x, y],
+        actual,
+        expected
+    );
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn alpha_test_discards_transparent_texels() {
+    let (device, queue) = device();
+    let mut context = TextureContext::from_parts(&device, &queue);
+    let texture = texture(&mut context, [2, 1], &[255, 0, 0, 255, 0, 255, 0, 0]);
+    let draw = |threshold| {
+        render(
+            &device,
+            &queue,
+            &Wgpu2dSettings::new(),
+            FORMAT,
+            [2, 1],
+            |c, g| {
+                clear([0.0, 0.0, 1.0, 1.0], g);
+                g.set_alpha_test(threshold);
+                // Without blending, texels which aren't discarded overwrite the background.
+                let draw_state = DrawState {
+                    blend: None,
+                    ..c.draw_state
+                };
+                Image::new()
+                    .rect([0.0, 0.0, 2.0, 1.0])
+                    .draw(&texture, &draw_state, c.transform, g);
+            },
+        )
+    };
+
+    let tested = draw(Some(0.5));
+    assert_pixel(&tested, [0, 0], [255, 0, 0, 255], 0);
+    assert_pixel(&tested, [1, 0], [0, 0, 255, 255], 0);
+    let untested = draw(None);
+    assert_pixel(&untested, [1, 0], [0, 255, 0, 0], 0);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn translucent_draws_inside_clip_composite() {
+    let (device, queue) = device();
+    let frame = render(
+        &device,
+        &queue,
+        &Wgpu2dSettings::new(),
+        FORMAT,
+        [6, 1],
+        |c, g| {
+            clear([0.0, 0.0, 0.0, 1.0], g);
+            Rectangle::new([1.0; 4]).draw(
+                [0.0, 0.0, 4.0, 1.0],
+                &DrawState::new_clip(),
+                c.transform,
+                g,
+            );
+            // Each draw is tested against the clip mask without changing it, so the following ones still pass.
+            for x in 0..3 {
+                Rectangle::new([1.0, 0.0, 0.0, 0.5]).draw(
+                    [x as f64, 0.0, 3.0, 1.0],
+                    &DrawState::new_inside(),
+                    c.transform,
+                    g,
+                );
+            }
+        },
+    );
+
+    // Pixels covered by 1, 2 and 3 layers of 50% red, then 2 layers, then pixels outside the clip.
+    let expected = [128, 191, 223, 191, 0, 0];
+    for (x, &red) in expected.iter().enumerate() {
+        assert_pixel(&frame, [x as u32, 0], [red, 0, 0, 255], 1);
+    }
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn linear_tint_multiplies_in_linear_space() {
+    let (device, queue) = device();
+    let mut context = TextureContext::from_parts(&device, &queue);
+    let texture = texture(&mut context, [1, 1], &[255; 4]);
+    let draw = |linear_tint| {
+        render(
+            &device,
+            &queue,
+            &Wgpu2dSettings::new().linear_tint(linear_tint),
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            [1, 1],
+            |c, g| {
+                Image::new_color([0.5, 0.5, 0.5, 1.0])
+                    .rect([0.0, 0.0, 1.0, 1.0])
+                    .draw(&texture, &c.draw_state, c.transform, g);
+            },
+        )
+    };
+
+    // White multiplied by the linear value of 50% gray is encoded back to 50% gray in the sRGB target.
+    assert_pixel(&draw(true), [0, 0], [128, 128, 128, 255], 1);
+    // Multiplying by 0.5 as a linear value gives a lighter gray, whose sRGB encoding is 0.735.
+    assert_pixel(&draw(false), [0, 0], [188, 188, 188, 255], 1);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn premultiplied_text_blends_glyph_edges() {
+    let (device, queue) = device();
+    let mut glyph_cache = GlyphCache::new(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/assets/FiraSans-Regular.ttf"),
+        TextureContext::from_parts(&device, &queue),
+        TextureSettings::new(),
+    )
+    .unwrap();
+    let mut draw = |premultiplied| {
+        render(
+            &device,
+            &queue,
+            &Wgpu2dSettings::new(),
+            FORMAT,
+            [32, 40],
+            |c, g| {
+                clear([0.0, 0.0, 1.0, 1.0], g);
+                let mut text = |g: &mut WgpuGraphics| {
+                    Text::new_color([1.0, 0.0, 0.0, 1.0], 32)
+                        .draw(
+                            "O",
+                            &mut glyph_cache,
+                            &c.draw_state,
+                            c.transform.trans(4.0, 32.0),
+                            g,
+                        )
+                        .unwrap();
+                };
+                if premultiplied {
+                    g.with_premultiplied_text(text);
+                } else {
+                    text(g);
+                }
+            },
+        )
+    };
+    let before = draw(false);
+    let after = draw(true);
+
+    // Pixels on the edges of the glyph are partially covered, so they mix the text and background colors.
+    let edges: Vec<_> = after
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| (32..224).contains(&pixel[0]))
+        .map(|(x, y, _)| [x, y])
+        .collect();
+    assert!(!edges.is_empty());
+    for &[x, y] in &edges {
+        let [red, _, blue, _] = after.get_pixel(x, y).0;
+        // Coverage weighs red against blue, without any dark or colored fringe.
+        assert_pixel(&after, [x, y], [red, 0, 255 - red, 255], 1);
+        // Glyph textures are white, with coverage in their alpha, so straight alpha blending already mixes them the same way.
+        assert_pixel(&before, [x, y], [red, 0, blue, 255], 1);
+    }
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn compact_vertices_look_the_same() {
+    let (device, queue) = device();
+    let mut context = TextureContext::from_parts(&device, &queue);
+    let texture = texture(&mut context, [2, 1], &[255, 128, 0, 255, 0, 64, 255, 200]);
+    let draw = |compact_vertices| {
+        render(
+            &device,
+            &queue,
+            &Wgpu2dSettings::new().compact_vertices(compact_vertices),
+            FORMAT,
+            [4, 2],
+            |c, g| {
+                clear([0.0, 0.0, 0.0, 1.0], g);
+                Rectangle::new([0.3, 0.6, 0.9, 0.7]).draw(
+                    [0.0, 0.0, 4.0, 1.0],
+                    &c.draw_state,
+                    c.transform,
+                    g,
+                );
+                Image::new_color([0.9, 0.8, 0.7, 0.6])
+                    .rect([0.0, 1.0, 4.0, 1.0])
+                    .draw(&texture, &c.draw_state, c.transform, g);
+            },
+        )
+    };
+    let full = draw(false);
+    let compact = draw(true);
+
+    for (x, y, pixel) in full.enumerate_pixels() {
+        assert_pixel(&compact, [x, y], pixel.0, 1);
+    }
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn line_list_validates_positions() {
+    let (device, queue) = device();
+    render(
+        &device,
+        &queue,
+        &Wgpu2dSettings::new().debug_validate(true),
+        FORMAT,
+        [1, 1],
+        |c, g| {
+            g.line_list(
+                &c.draw_state,
+                &[[0.0, 0.0], [1.0, 1.0], [f64::NAN, 0.0], [1.0, 0.0]],
+                c.transform,
+                [1.0; 4],
+            );
+            let invalid = g.invalid_vertices();
+            assert_eq!(invalid.len(), 1);
+            assert_eq!(invalid[0].index, 2);
+        },
+    );
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn cached_layer_submitted_with_frame() {
+    let (device, queue) = device();
+    let size = [2, 1];
+    let wgpu2d = Wgpu2d::with_settings(&device, &config(FORMAT, size), &Wgpu2dSettings::new());
+    let settings = TextureSettings::new().filter(Filter::Nearest);
+    let mut layer = CachedLayer::new(&wgpu2d, &device, size, [1.0, 0.0, 0.0, 1.0], &settings);
+    let target = wgpu2d.create_render_target(&device, size, &TextureSettings::new());
+
+    // Both command buffers upload vertices before either is submitted.
+    let layer_commands = layer
+        .update(&wgpu2d, &device, |c, g| {
+            Rectangle::new([0.0, 1.0, 0.0, 1.0]).draw(
+                [0.0, 0.0, 1.0, 1.0],
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+        })
+        .unwrap();
+    let frame_commands = wgpu2d
+        .draw_to_texture(&device, &target, |c, g| layer.draw(g, c.transform))
+        .unwrap();
+    queue.submit(layer_commands.into_iter().chain(Some(frame_commands)));
+
+    let frame = Wgpu2d::capture(&device, &queue, target.as_wgpu()).unwrap();
+    assert_pixel(&frame, [0, 0], [0, 255, 0, 255], 0);
+    assert_pixel(&frame, [1, 0], [255, 0, 0, 255], 0);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn premultiplied_vertex_colors_compose_add_and_alpha() {
+    let (device, queue) = device();
+    let draw = |premultiply_vertex_colors| {
+        render(
+            &device,
+            &queue,
+            &Wgpu2dSettings::new().premultiply_vertex_colors(premultiply_vertex_colors),
+            FORMAT,
+            [1, 1],
+            |c, g| {
+                clear([0.0, 0.0, 0.0, 1.0], g);
+                let rect = [0.0, 0.0, 1.0, 1.0];
+                Rectangle::new([0.4, 0.2, 0.0, 0.5]).draw(
+                    rect,
+                    &c.draw_state.blend(Blend::Add),
+                    c.transform,
+                    g,
+                );
+                Rectangle::new([0.0, 0.0, 1.0, 0.5]).draw(
+                    rect,
+                    &c.draw_state.blend(Blend::Alpha),
+                    c.transform,
+                    g,
+                );
+            },
+        )
+    };
+
+    // The added color is scaled by its alpha to [0.2, 0.1, 0.0], then half covered by half of the blue.
+    assert_pixel(&draw(true), [0, 0], [26, 13, 128, 255], 1);
+    // Otherwise, the added color ignores its alpha.
+    assert_pixel(&draw(false), [0, 0], [51, 26, 128, 255], 1);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn accumulate_keeps_previous_frames() {
+    let (device, queue) = device();
+    let size = [2, 1];
+    let config = config(FORMAT, size);
+    let mut wgpu2d =
+        Wgpu2d::with_settings(&device, &config, &Wgpu2dSettings::new().accumulate(true));
+    let viewport = Viewport {
+        rect: [0, 0, 2, 1],
+        draw_size: size,
+        window_size: [2.0, 1.0],
+    };
+    // Frames are drawn onto different outputs, as surface textures can be.
+    let outputs = [
+        wgpu2d.create_render_target(&device, size, &TextureSettings::new()),
+        wgpu2d.create_render_target(&device, size, &TextureSettings::new()),
+    ];
+    let rects = [[0.0, 0.0, 1.0, 1.0], [1.0, 0.0, 1.0, 1.0]];
+    let colors = [[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0]];
+    for ((output, rect), color) in outputs.iter().zip(rects).zip(colors) {
+        let view = output
+            .as_wgpu()
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let command_buffer = wgpu2d.draw(&device, &config, &view, viewport, |c, g| {
+            Rectangle::new(color).draw(rect, &c.draw_state, c.transform, g);
+        });
+        queue.submit(Some(command_buffer));
+    }
+
+    let frame = Wgpu2d::capture(&device, &queue, outputs[1].as_wgpu()).unwrap();
+    assert_pixel(&frame, [0, 0], [255, 0, 0, 255], 0);
+    assert_pixel(&frame, [1, 0], [0, 255, 0, 255], 0);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn stencil_starts_cleared_each_frame() {
+    let (device, queue) = device();
+    let size = [2, 1];
+    let wgpu2d = Wgpu2d::with_settings(&device, &config(FORMAT, size), &Wgpu2dSettings::new());
+    let targets = [
+        wgpu2d.create_render_target(&device, size, &TextureSettings::new()),
+        wgpu2d.create_render_target(&device, size, &TextureSettings::new()),
+    ];
+    // The first frame leaves a clip mask over its left pixel.
+    let clip = wgpu2d
+        .draw_to_texture(&device, &targets[0], |c, g| {
+            Rectangle::new([1.0; 4]).draw(
+                [0.0, 0.0, 1.0, 1.0],
+                &DrawState::new_clip(),
+                c.transform,
+                g,
+            );
+        })
+        .unwrap();
+    let inside = wgpu2d
+        .draw_to_texture(&device, &targets[1], |c, g| {
+            // Unlike `clear`, this leaves the stencil as it is.
+            g.clear_color([0.0, 0.0, 1.0, 1.0]);
+            Rectangle::new([1.0, 0.0, 0.0, 1.0]).draw(
+                [0.0, 0.0, 2.0, 1.0],
+                &DrawState::new_inside(),
+                c.transform,
+                g,
+            );
+        })
+        .unwrap();
+    queue.submit([clip, inside]);
+
+    let frame = Wgpu2d::capture(&device, &queue, targets[1].as_wgpu()).unwrap();
+    assert_pixel(&frame, [0, 0], [0, 0, 255, 255], 0);
+    assert_pixel(&frame, [1, 0], [0, 0, 255, 255], 0);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn multisampled_frames_of_one_size_are_resolved_apart() {
+    let (device, queue) = device();
+    let size = [2, 1];
+    let settings = Wgpu2dSettings::new().sample_count(4);
+    let wgpu2d = Wgpu2d::with_settings(&device, &config(FORMAT, size), &settings);
+    let targets = [
+        wgpu2d.create_render_target(&device, size, &TextureSettings::new()),
+        wgpu2d.create_render_target(&device, size, &TextureSettings::new()),
+    ];
+    // Both frames are drawn through the same multisampled texture before either is submitted.
+    let rects = [[0.0, 0.0, 1.0, 1.0], [1.0, 0.0, 1.0, 1.0]];
+    let command_buffers = targets.iter().zip(rects).map(|(target, rect)| {
+        wgpu2d
+            .draw_to_texture(&device, target, |c, g| {
+                clear([0.0, 0.0, 1.0, 1.0], g);
+                Rectangle::new([1.0, 0.0, 0.0, 1.0]).draw(rect, &c.draw_state, c.transform, g);
+            })
+            .unwrap()
+    });
+    queue.submit(command_buffers.collect::<Vec<_>>());
+
+    let first = Wgpu2d::capture(&device, &queue, targets[0].as_wgpu()).unwrap();
+    assert_pixel(&first, [0, 0], [255, 0, 0, 255], 0);
+    assert_pixel(&first, [1, 0], [0, 0, 255, 255], 0);
+    let second = Wgpu2d::capture(&device, &queue, targets[1].as_wgpu()).unwrap();
+    assert_pixel(&second, [0, 0], [0, 0, 255, 255], 0);
+    assert_pixel(&second, [1, 0], [255, 0, 0, 255], 0);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn multitexture_samples_second_texture_at_its_own_coordinates() {
+    let (device, queue) = device();
+    let mut context = TextureContext::from_parts(&device, &queue);
+    let base = texture(&mut context, [1, 1], &[255, 255, 255, 255]);
+    let second = texture(&mut context, [2, 1], &[255, 0, 0, 255, 0, 255, 0, 255]);
+    let frame = render(
+        &device,
+        &queue,
+        &Wgpu2dSettings::new(),
+        FORMAT,
+        [2, 1],
+        |c, g| {
+            let xys = [
+                [0.0, 0.0],
+                [2.0, 0.0],
+                [2.0, 1.0],
+                [0.0, 0.0],
+                [2.0, 1.0],
+                [0.0, 1.0],
+            ];
+            let uvs = [[0.0, 0.0]; 6];
+            // Their second coordinates are 0, which GPUs flushing denormals used to lose the first ones with.
+            let uvs2 = [
+                [0.0, 0.0],
+                [1.0, 0.0],
+                [1.0, 0.0],
+                [0.0, 0.0],
+                [1.0, 0.0],
+                [0.0, 0.0],
+            ];
+            g.tri_list_uv2(
+                &c.draw_state,
+                &base,
+                &second,
+                MultitextureMode::Multiply,
+                1.0,
+                &xys,
+                c.transform,
+                &uvs,
+                &uvs2,
+            );
+        },
+    );
+    assert_pixel(&frame, [0, 0], [255, 0, 0, 255], 0);
+    assert_pixel(&frame, [1, 0], [0, 255, 0, 255], 0);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn mipmapped_textures_refuse_updates() {
+    let (device, queue) = device();
+    let mut context = TextureContext::from_parts(&device, &queue);
+    let image = image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255]));
+    let settings = TextureSettings::new().generate_mipmap(true);
+    let mut texture = Texture::from_image(&mut context, &image, &settings).unwrap();
+    let update = image::RgbaImage::from_pixel(2, 2, image::Rgba([0, 255, 0, 255]));
+
+    let result = texture.update_from_image(&mut context, &update.into(), [0, 0]);
+    assert!(matches!(result, Err(TextureError::MipmappedUpdate)));
+}